@@ -0,0 +1,73 @@
+use crate::ray::Ray;
+use crate::vec;
+use crate::vec::Point;
+use crate::vec::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    pub fn new(a: Point, b: Point) -> Self {
+        Self {
+            min: vec::min(a, b),
+            max: vec::max(a, b),
+        }
+    }
+
+    pub fn surrounding(a: Aabb, b: Aabb) -> Self {
+        Self {
+            min: vec::min(a.min, b.min),
+            max: vec::max(a.max, b.max),
+        }
+    }
+
+    // Grown by a small margin on every side, so flat shapes such as
+    // axis-aligned triangles don't get zero-width boxes.
+    pub fn padded(&self) -> Self {
+        let padding = Vec3 {
+            x: 1e-4,
+            y: 1e-4,
+            z: 1e-4,
+        };
+        Self {
+            min: self.min - padding,
+            max: self.max + padding,
+        }
+    }
+
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test; a zero direction component yields infinities which min/max absorb.
+    pub fn hit(&self, r: Ray, mut t_min: f32, mut t_max: f32) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.d[axis];
+            let mut t0 = (self.min[axis] - r.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,290 @@
+use crate::error::ValidationError;
+use crate::hdr;
+use crate::hdr::HdrError;
+use crate::hdr::HdrImage;
+use crate::hittable::validate_color;
+use crate::ray::Ray;
+use crate::vec::dot;
+use crate::vec::Color;
+use crate::vec::Vec3;
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Background {
+    Solid { color: Color },
+    Gradient { bottom: Color, top: Color },
+    EnvironmentMap(EnvironmentMap),
+    SunSky(SunSky),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Gradient {
+            bottom: Color {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            top: Color {
+                x: 0.5,
+                y: 0.7,
+                z: 1.0,
+            },
+        }
+    }
+}
+
+impl Background {
+    pub fn color(&self, r: Ray) -> Color {
+        match self {
+            Background::Solid { color } => *color,
+            Background::Gradient { bottom, top } => {
+                let t = (r.d.unit().y + 1.0) / 2.0;
+                *bottom * (1.0 - t) + *top * t
+            }
+            Background::EnvironmentMap(m) => m.color(r.d.unit()),
+            Background::SunSky(s) => s.color(r.d.unit()),
+        }
+    }
+
+    pub fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        let mut non_negative = |value: f32, field: &str| {
+            if !(value >= 0.0 && value.is_finite()) {
+                errors.push(ValidationError::new(
+                    format!("{}.{}", path, field),
+                    "must be finite and non-negative",
+                ));
+            }
+        };
+        match self {
+            Background::Solid { color } => {
+                validate_color(*color, format!("{}.color", path), errors)
+            }
+            Background::Gradient { bottom, top } => {
+                validate_color(*bottom, format!("{}.bottom", path), errors);
+                validate_color(*top, format!("{}.top", path), errors);
+            }
+            Background::EnvironmentMap(m) => non_negative(m.intensity, "intensity"),
+            Background::SunSky(s) => {
+                non_negative(s.intensity, "intensity");
+                non_negative(s.sun_radius, "sun_radius");
+                non_negative(s.sun_intensity, "sun_intensity");
+                let d = s.sun_direction;
+                if !d.is_finite() || d.length() == 0.0 {
+                    errors.push(ValidationError::new(
+                        format!("{}.sun_direction", path),
+                        "must be a finite, non-zero vector",
+                    ));
+                }
+                // The Preetham fit is only meaningful for hazy to clear skies.
+                if !(1.0..=20.0).contains(&s.turbidity) {
+                    errors.push(ValidationError::new(
+                        format!("{}.turbidity", path),
+                        "must be between 1 and 20",
+                    ));
+                }
+            }
+        }
+    }
+
+    // Loads the files a background refers to, resolved relative to `base_dir`,
+    // adding their paths to `files`.
+    pub fn load(&mut self, base_dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), HdrError> {
+        if let Background::EnvironmentMap(m) = self {
+            let path = base_dir.join(&m.path);
+            m.image = Some(hdr::load_hdr(&path)?);
+            files.push(path);
+        }
+        Ok(())
+    }
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+// Equirectangular Radiance .hdr map with +y up and its center facing -z.
+#[derive(Serialize, Deserialize)]
+pub struct EnvironmentMap {
+    pub path: PathBuf,
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+    // Rotation around the y axis, in degrees.
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(skip)]
+    image: Option<HdrImage>,
+}
+
+impl EnvironmentMap {
+    fn color(&self, d: Vec3) -> Color {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return Default::default(),
+        };
+        let phi = d.x.atan2(-d.z) + self.rotation / 180.0 * PI;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        image.sample(u, v) * self.intensity
+    }
+}
+
+fn default_turbidity() -> f32 {
+    3.0
+}
+
+fn default_sun_radius() -> f32 {
+    0.5
+}
+
+fn default_sun_intensity() -> f32 {
+    50.0
+}
+
+// Preetham et al., "A Practical Analytic Model for Daylight", plus a sun disk.
+// Sky luminance is normalised so the zenith has the given intensity.
+#[derive(Serialize, Deserialize)]
+pub struct SunSky {
+    // Direction towards the sun.
+    pub sun_direction: Vec3,
+    #[serde(default = "default_turbidity")]
+    pub turbidity: f32,
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+    // Angular radius of the sun disk, in degrees.
+    #[serde(default = "default_sun_radius")]
+    pub sun_radius: f32,
+    #[serde(default = "default_sun_intensity")]
+    pub sun_intensity: f32,
+    #[serde(skip)]
+    model: OnceLock<PreethamModel>,
+}
+
+// Perez distribution coefficients and zenith value for one of Y, x, y.
+struct PerezChannel {
+    coefficients: [f32; 5],
+    zenith: f32,
+    // F(0, theta_s), the distribution at the zenith.
+    zenith_perez: f32,
+}
+
+impl PerezChannel {
+    fn new(coefficients: [f32; 5], zenith: f32, theta_s: f32) -> Self {
+        let mut channel = Self {
+            coefficients,
+            zenith,
+            zenith_perez: 1.0,
+        };
+        channel.zenith_perez = channel.perez(1.0, theta_s);
+        channel
+    }
+
+    fn perez(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.coefficients;
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+
+    fn value(&self, cos_theta: f32, gamma: f32) -> f32 {
+        self.zenith * self.perez(cos_theta, gamma) / self.zenith_perez
+    }
+}
+
+struct PreethamModel {
+    sun: Vec3,
+    luminance: PerezChannel,
+    x: PerezChannel,
+    y: PerezChannel,
+}
+
+impl PreethamModel {
+    fn new(sun: Vec3, t: f32) -> Self {
+        let theta_s = sun.y.clamp(0.0, 1.0).acos();
+        let (t2, s, s2, s3) = (t * t, theta_s, theta_s * theta_s, theta_s.powi(3));
+
+        let zenith_x = t2 * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let zenith_y = t2 * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        Self {
+            sun,
+            // Absolute zenith luminance is dropped in favour of SunSky::intensity.
+            luminance: PerezChannel::new(
+                [
+                    0.1787 * t - 1.4630,
+                    -0.3554 * t + 0.4275,
+                    -0.0227 * t + 5.3251,
+                    0.1206 * t - 2.5771,
+                    -0.0670 * t + 0.3703,
+                ],
+                1.0,
+                theta_s,
+            ),
+            x: PerezChannel::new(
+                [
+                    -0.0193 * t - 0.2592,
+                    -0.0665 * t + 0.0008,
+                    -0.0004 * t + 0.2125,
+                    -0.0641 * t - 0.8989,
+                    -0.0033 * t + 0.0452,
+                ],
+                zenith_x,
+                theta_s,
+            ),
+            y: PerezChannel::new(
+                [
+                    -0.0167 * t - 0.2608,
+                    -0.0950 * t + 0.0092,
+                    -0.0079 * t + 0.2102,
+                    -0.0441 * t - 1.6537,
+                    -0.0109 * t + 0.0529,
+                ],
+                zenith_y,
+                theta_s,
+            ),
+        }
+    }
+
+    fn color(&self, d: Vec3) -> Color {
+        // The model is undefined below the horizon, so hold the horizon value there.
+        let cos_theta = d.y.max(0.01);
+        let gamma = dot(d, self.sun).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.luminance.value(cos_theta, gamma);
+        let x = self.x.value(cos_theta, gamma);
+        let y = self.y.value(cos_theta, gamma).max(1e-4);
+
+        let cx = x / y * luminance;
+        let cz = (1.0 - x - y) / y * luminance;
+        Color {
+            x: (3.2406 * cx - 1.5372 * luminance - 0.4986 * cz).max(0.0),
+            y: (-0.9689 * cx + 1.8758 * luminance + 0.0415 * cz).max(0.0),
+            z: (0.0557 * cx - 0.2040 * luminance + 1.0570 * cz).max(0.0),
+        }
+    }
+}
+
+impl SunSky {
+    fn color(&self, d: Vec3) -> Color {
+        let model = self
+            .model
+            .get_or_init(|| PreethamModel::new(self.sun_direction.unit(), self.turbidity));
+        let sky = model.color(d) * self.intensity;
+        if dot(d, model.sun) >= (self.sun_radius / 180.0 * PI).cos() {
+            return sky
+                + Color {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 1.0,
+                } * self.sun_intensity;
+        }
+        sky
+    }
+}
@@ -1,14 +1,22 @@
 use raytracing::camera;
-use raytracing::rand;
+use raytracing::rand::Rng;
 use raytracing::scene::Scene;
 
+use raytracing::background::Background;
 use raytracing::hittable;
+use raytracing::plane::Plane;
+use raytracing::quad::Cuboid;
+use raytracing::quad::Quad;
 use raytracing::sphere::Sphere;
+use raytracing::transform::Rotation;
+use raytracing::transform::Transform;
+use raytracing::transform::TransformStep;
 use raytracing::vec::Color;
 use raytracing::vec::Point;
 use raytracing::vec::Vec3;
 
 use hittable::Dielectric;
+use hittable::DiffuseLight;
 use hittable::Lambertian;
 use hittable::Material;
 use hittable::Metal;
@@ -66,13 +74,17 @@ fn get_scene() -> Scene {
         }),
     });
     w.add_object(Object {
-        hittable: Box::new(Sphere {
-            o: Point {
+        hittable: Box::new(Plane {
+            p: Point {
                 x: 0.0,
-                y: -500.5,
-                z: -1.0,
+                y: -0.5,
+                z: 0.0,
+            },
+            n: Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
             },
-            r: 500.0,
         }),
         material: Box::new(Lambertian {
             albedo: Color {
@@ -99,8 +111,8 @@ fn get_scene() -> Scene {
         z: 0.0,
     };
 
-    Scene {
-        camera_config: camera::CameraConfig {
+    Scene::new(
+        camera::CameraConfig {
             lookfrom,
             lookat,
             vup,
@@ -109,24 +121,23 @@ fn get_scene() -> Scene {
             aperture: 0.1,
             focus_dist: 2.0,
         },
-        world: w,
-    }
+        w,
+        Default::default(),
+    )
 }
 
 fn generate_scene() -> Scene {
+    let mut rng = Rng::from_entropy();
     let mut w: World = Default::default();
 
-    let earth_radius = 500.0;
-    let earth_center = Point {
-        x: 0.0,
-        y: -earth_radius,
-        z: 0.0,
-    };
-
     w.add_object(Object {
-        hittable: Box::new(Sphere {
-            o: earth_center,
-            r: earth_radius,
+        hittable: Box::new(Plane {
+            p: Default::default(),
+            n: Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
         }),
         material: Box::new(Lambertian {
             albedo: Color {
@@ -140,32 +151,31 @@ fn generate_scene() -> Scene {
     for xi in -10..10 {
         for zi in -10..10 {
             let (x, z) = (
-                (xi * 3) as f32 + rand::get_random_offset(),
-                (zi * 3) as f32 + rand::get_random_offset(),
+                (xi * 3) as f32 + rng.offset(),
+                (zi * 3) as f32 + rng.offset(),
             );
             let r = 0.5;
-            let c =
-                (Point { x, y: 0.0, z } - earth_center).unit() * (earth_radius + r) + earth_center;
+            let c = Point { x, y: r, z };
 
-            let material_p = rand::get_random_float();
+            let material_p = rng.float();
             let material: Box<dyn Material>;
             if material_p > 0.8 {
                 material = Box::new(Metal {
                     albedo: Color {
-                        x: rand::get_random_float(),
-                        y: rand::get_random_float(),
-                        z: rand::get_random_float(),
+                        x: rng.float(),
+                        y: rng.float(),
+                        z: rng.float(),
                     },
-                    fuzziness: 0.1 * rand::get_random_float(),
+                    fuzziness: 0.1 * rng.float(),
                 });
             } else if material_p > 0.7 {
                 material = Box::new(Dielectric { ir: 1.5 });
             } else {
                 material = Box::new(Lambertian {
                     albedo: Color {
-                        x: rand::get_random_float(),
-                        y: rand::get_random_float(),
-                        z: rand::get_random_float(),
+                        x: rng.float(),
+                        y: rng.float(),
+                        z: rng.float(),
                     },
                 });
             }
@@ -192,8 +202,8 @@ fn generate_scene() -> Scene {
         z: 0.0,
     };
 
-    Scene {
-        camera_config: camera::CameraConfig {
+    Scene::new(
+        camera::CameraConfig {
             lookfrom,
             lookat,
             vup,
@@ -202,14 +212,122 @@ fn generate_scene() -> Scene {
             aperture: 0.1,
             focus_dist: 12.0,
         },
-        world: w,
+        w,
+        Default::default(),
+    )
+}
+
+// The Cornell box, in its original 555 unit cube, with two white boxes
+// under a light in the ceiling.
+fn cornell_box() -> Scene {
+    let mut w: World = Default::default();
+    let lambertian = |x, y, z| -> Box<dyn Material> {
+        Box::new(Lambertian {
+            albedo: Color { x, y, z },
+        })
+    };
+    let v = |x, y, z| Vec3 { x, y, z };
+
+    let walls = [
+        // Green and red side walls, floor, ceiling and back.
+        (
+            v(555.0, 0.0, 0.0),
+            v(0.0, 555.0, 0.0),
+            v(0.0, 0.0, 555.0),
+            (0.12, 0.45, 0.15),
+        ),
+        (
+            v(0.0, 0.0, 0.0),
+            v(0.0, 555.0, 0.0),
+            v(0.0, 0.0, 555.0),
+            (0.65, 0.05, 0.05),
+        ),
+        (
+            v(0.0, 0.0, 0.0),
+            v(555.0, 0.0, 0.0),
+            v(0.0, 0.0, 555.0),
+            (0.73, 0.73, 0.73),
+        ),
+        (
+            v(555.0, 555.0, 555.0),
+            v(-555.0, 0.0, 0.0),
+            v(0.0, 0.0, -555.0),
+            (0.73, 0.73, 0.73),
+        ),
+        (
+            v(0.0, 0.0, 555.0),
+            v(555.0, 0.0, 0.0),
+            v(0.0, 555.0, 0.0),
+            (0.73, 0.73, 0.73),
+        ),
+    ];
+    for (q, u, v, (r, g, b)) in walls {
+        w.add_object(Object {
+            hittable: Box::new(Quad { q, u, v }),
+            material: lambertian(r, g, b),
+        });
+    }
+
+    // Facing down into the box.
+    w.add_object(Object {
+        hittable: Box::new(Quad {
+            q: v(213.0, 554.0, 227.0),
+            u: v(130.0, 0.0, 0.0),
+            v: v(0.0, 0.0, 105.0),
+        }),
+        material: Box::new(DiffuseLight {
+            color: v(1.0, 1.0, 1.0),
+            intensity: 15.0,
+            two_sided: false,
+        }),
+    });
+
+    // The two boxes, turned to face the walls.
+    let boxes = [
+        (165.0, -18.0, v(130.0, 0.0, 65.0)),
+        (330.0, 15.0, v(265.0, 0.0, 295.0)),
+    ];
+    for (height, degrees, position) in boxes {
+        w.add_object(Object {
+            hittable: Box::new(Transform::new(
+                vec![
+                    TransformStep::Rotate(Rotation::AxisAngle {
+                        axis: v(0.0, 1.0, 0.0),
+                        degrees,
+                    }),
+                    TransformStep::Translate(position),
+                ],
+                Box::new(Cuboid {
+                    min: v(0.0, 0.0, 0.0),
+                    max: v(165.0, height, 165.0),
+                }),
+            )),
+            material: lambertian(0.73, 0.73, 0.73),
+        });
     }
+
+    Scene::new(
+        camera::CameraConfig {
+            lookfrom: v(278.0, 278.0, -800.0),
+            lookat: v(278.0, 278.0, 0.0),
+            vup: v(0.0, 1.0, 0.0),
+            vfov: 40.0,
+            aspect_ratio: 1.0,
+            aperture: 0.0,
+            focus_dist: 800.0,
+        },
+        w,
+        Background::Solid {
+            color: Default::default(),
+        },
+    )
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let scene = match args[1].as_str() {
         "static" => get_scene(),
+        "cornell" => cornell_box(),
         _ => generate_scene(),
     };
     println!("{}", serde_json::to_string_pretty(&scene).unwrap());
@@ -0,0 +1,235 @@
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+
+const MAX_LEAF_SIZE: usize = 2;
+
+enum BvhNode {
+    Leaf {
+        bbox: Aabb,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        bbox: Aabb,
+        left: usize,
+        right: usize,
+        axis: usize,
+    },
+}
+
+impl BvhNode {
+    fn bbox(&self) -> Aabb {
+        match self {
+            BvhNode::Leaf { bbox, .. } | BvhNode::Interior { bbox, .. } => *bbox,
+        }
+    }
+}
+
+// Bounding volume hierarchy over arbitrary primitives, identified by the ids
+// they were built with. Nodes are stored flat with the root at index 0.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    ids: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(items: Vec<(usize, Aabb)>) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * items.len()),
+            ids: Vec::with_capacity(items.len()),
+        };
+        if !items.is_empty() {
+            let mut items = items;
+            bvh.build(&mut items);
+        }
+        bvh
+    }
+
+    fn build(&mut self, items: &mut [(usize, Aabb)]) -> usize {
+        let bbox = items
+            .iter()
+            .map(|(_, b)| *b)
+            .reduce(Aabb::surrounding)
+            .unwrap();
+
+        let node_index = self.nodes.len();
+        if items.len() <= MAX_LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf {
+                bbox,
+                start: self.ids.len(),
+                count: items.len(),
+            });
+            self.ids.extend(items.iter().map(|(id, _)| *id));
+            return node_index;
+        }
+
+        let centroid_bounds = items
+            .iter()
+            .map(|(_, b)| Aabb::new(b.centroid(), b.centroid()))
+            .reduce(Aabb::surrounding)
+            .unwrap();
+        let axis = centroid_bounds.longest_axis();
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |(_, a), (_, b)| {
+            a.centroid()[axis].total_cmp(&b.centroid()[axis])
+        });
+
+        // Reserve the slot so children land after their parent.
+        self.nodes.push(BvhNode::Interior {
+            bbox,
+            left: 0,
+            right: 0,
+            axis,
+        });
+        let (left_items, right_items) = items.split_at_mut(mid);
+        let left = self.build(left_items);
+        let right = self.build(right_items);
+        self.nodes[node_index] = BvhNode::Interior {
+            bbox,
+            left,
+            right,
+            axis,
+        };
+        node_index
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(BvhNode::bbox)
+    }
+
+    // Calls `f` with the id of every primitive whose box the ray crosses within [t_min, t_max].
+    pub fn for_each_candidate<F: FnMut(usize)>(&self, r: Ray, t_min: f32, t_max: f32, mut f: F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if !node.bbox().hit(r, t_min, t_max) {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { start, count, .. } => {
+                    self.ids[start..start + count].iter().for_each(|&id| f(id));
+                }
+                BvhNode::Interior { left, right, .. } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+
+    // Visits candidates front to back, shrinking t_max to every hit `f` reports
+    // as (t, payload), and returns the payload of the nearest one.
+    pub fn closest_hit<T, F>(&self, r: Ray, t_min: f32, mut t_max: f32, mut f: F) -> Option<T>
+    where
+        F: FnMut(usize, f32) -> Option<(f32, T)>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest = None;
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if !node.bbox().hit(r, t_min, t_max) {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { start, count, .. } => {
+                    for &id in &self.ids[start..start + count] {
+                        if let Some((t, payload)) = f(id, t_max) {
+                            t_max = t;
+                            closest = Some(payload);
+                        }
+                    }
+                }
+                BvhNode::Interior {
+                    left, right, axis, ..
+                } => {
+                    if r.d[axis] < 0.0 {
+                        stack.push(left);
+                        stack.push(right);
+                    } else {
+                        stack.push(right);
+                        stack.push(left);
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aabb;
+    use super::Bvh;
+    use crate::ray::Ray;
+    use crate::vec::Point;
+    use crate::vec::Vec3;
+
+    fn unit_box_at(x: f32) -> Aabb {
+        Aabb::new(
+            Point {
+                x: x - 0.5,
+                y: -0.5,
+                z: -0.5,
+            },
+            Point {
+                x: x + 0.5,
+                y: 0.5,
+                z: 0.5,
+            },
+        )
+    }
+
+    #[test]
+    fn test_for_each_candidate() {
+        let bvh = Bvh::new((0..10).map(|i| (i, unit_box_at(2.0 * i as f32))).collect());
+        let r = Ray {
+            origin: Point {
+                x: 6.0,
+                y: -5.0,
+                z: 0.0,
+            },
+            d: Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        };
+
+        let mut candidates = Vec::new();
+        bvh.for_each_candidate(r, 0.0, f32::MAX, |id| candidates.push(id));
+        assert!(candidates.contains(&3));
+        assert!(candidates.len() <= super::MAX_LEAF_SIZE);
+    }
+
+    #[test]
+    fn test_closest_hit() {
+        let bvh = Bvh::new((0..10).map(|i| (i, unit_box_at(2.0 * i as f32))).collect());
+        let r = Ray {
+            origin: Point {
+                x: 7.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            d: Vec3 {
+                x: -1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        };
+
+        // Every box in front of the ray reports a hit at its near face.
+        let closest = bvh.closest_hit(r, 0.0, f32::MAX, |id, t_max| {
+            let t = 7.0 - (2.0 * id as f32 + 0.5);
+            (t >= 0.0 && t < t_max).then_some((t, id))
+        });
+        assert_eq!(closest, Some(3));
+    }
+}
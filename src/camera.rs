@@ -1,6 +1,9 @@
-use crate::rand;
+use crate::error::ValidationError;
+use crate::hittable::validate_point;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::cross;
+use crate::vec::dot;
 use crate::vec::Point;
 use crate::vec::Vec3;
 use serde::{Deserialize, Serialize};
@@ -27,6 +30,37 @@ pub struct CameraConfig {
     pub focus_dist: f32,
 }
 
+impl CameraConfig {
+    pub fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        let mut error = |field: &str, message: &str| {
+            errors.push(ValidationError::new(format!("{}.{}", path, field), message))
+        };
+        let view = self.lookfrom - self.lookat;
+        if view.is_finite() && view.length() == 0.0 {
+            error("lookat", "must differ from lookfrom");
+        } else if self.vup.is_finite() && self.vup.length() == 0.0 {
+            error("vup", "must be non-zero");
+        } else if dot(view.unit(), self.vup.unit()).abs() > 1.0 - 1e-6 {
+            error("vup", "must not be parallel to the viewing direction");
+        }
+        if !(self.vfov > 0.0 && self.vfov < 180.0) {
+            error("vfov", "must be between 0 and 180 degrees");
+        }
+        if !(self.aspect_ratio > 0.0 && self.aspect_ratio.is_finite()) {
+            error("aspect_ratio", "must be positive");
+        }
+        if !(self.aperture >= 0.0 && self.aperture.is_finite()) {
+            error("aperture", "must be finite and non-negative");
+        }
+        if !(self.focus_dist > 0.0 && self.focus_dist.is_finite()) {
+            error("focus_dist", "must be positive");
+        }
+        validate_point(self.lookfrom, format!("{}.lookfrom", path), errors);
+        validate_point(self.lookat, format!("{}.lookat", path), errors);
+        validate_point(self.vup, format!("{}.vup", path), errors);
+    }
+}
+
 impl Camera {
     pub fn new(config: CameraConfig) -> Self {
         let theta = config.vfov / 180.0 * PI;
@@ -57,8 +91,8 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd = self.lens_radius * rand::random_in_sphere();
+    pub fn get_ray(&self, u: f32, v: f32, sampler: &mut Sampler) -> Ray {
+        let rd = self.lens_radius * sampler.in_disk();
         let offset = self.u * rd.x + self.v * rd.y;
         Ray {
             origin: self.origin + offset,
@@ -0,0 +1,452 @@
+use crate::film::Film;
+use crate::film::Filter;
+use crate::renderer::Pass;
+use crate::renderer::PixelStats;
+use crate::renderer::RenderConfig;
+use crate::renderer::RenderState;
+use crate::sampler::SamplerKind;
+
+use serde::{Deserialize, Serialize};
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const MAGIC: &str = "raytracing checkpoint 1";
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(PathBuf, io::Error),
+    Format(PathBuf, String),
+    // The checkpoint was made with a different scene or settings.
+    Mismatch(PathBuf, String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            CheckpointError::Format(path, message) => {
+                write!(f, "{}: invalid checkpoint: {}", path.display(), message)
+            }
+            CheckpointError::Mismatch(path, message) => {
+                write!(f, "{}: cannot resume: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl error::Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CheckpointError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Everything that decides what a pixel's samples are, besides the seed.
+#[derive(Serialize, Deserialize)]
+struct Setup {
+    scene_hash: u64,
+    img_width: usize,
+    img_height: usize,
+    depth: u32,
+    sampler: SamplerKind,
+    // The stratified sampler splits a pixel into as many strata as it will
+    // take samples, so more samples only fit with ones for the same total.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    strata: Option<u32>,
+    filter: Filter,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    setup: Setup,
+    seed: u64,
+    passes: u32,
+    // The sample indices of the last pass.
+    samples: Range<u32>,
+    elapsed_seconds: f64,
+}
+
+impl Setup {
+    fn new(config: &RenderConfig, scene_hash: u64) -> Self {
+        Self {
+            scene_hash,
+            img_width: config.img_width,
+            img_height: config.img_height,
+            depth: config.depth,
+            sampler: config.sampler,
+            strata: (config.sampler == SamplerKind::Stratified).then(|| config.sample_range().1),
+            filter: config.filter,
+        }
+    }
+
+    // Describes the first difference from `other`.
+    fn difference(&self, other: &Setup) -> Option<String> {
+        if self.scene_hash != other.scene_hash {
+            return Some("the scene or a file it refers to has changed".to_string());
+        }
+        let fields = [
+            (
+                "image size",
+                format!("{}x{}", self.img_width, self.img_height),
+                format!("{}x{}", other.img_width, other.img_height),
+            ),
+            ("depth", self.depth.to_string(), other.depth.to_string()),
+            (
+                "sampler",
+                format!("{:?}", self.sampler),
+                format!("{:?}", other.sampler),
+            ),
+            (
+                "stratified samples per pixel",
+                self.strata.unwrap_or_default().to_string(),
+                other.strata.unwrap_or_default().to_string(),
+            ),
+            (
+                "filter",
+                format!("{:?}", self.filter),
+                format!("{:?}", other.filter),
+            ),
+        ];
+        fields
+            .into_iter()
+            .find(|(_, a, b)| a != b)
+            .map(|(name, a, b)| format!("it was rendered with {} {}, not {}", name, a, b))
+    }
+}
+
+// FNV-1a over the files' lengths and bytes, to notice a scene or one of its
+// meshes or images edited between sessions.
+pub fn hash_files<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Result<u64, CheckpointError> {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for path in paths {
+        let data = fs::read(path).map_err(|e| CheckpointError::Io(path.to_path_buf(), e))?;
+        for &b in (data.len() as u64).to_le_bytes().iter().chain(&data) {
+            h = (h ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    Ok(h)
+}
+
+// A text line naming the format, a JSON header, then the raw film and pixel
+// statistics. Written next to `path` and renamed over it, so an interrupted
+// save leaves the previous checkpoint intact.
+pub fn save(
+    path: &Path,
+    state: &RenderState,
+    config: &RenderConfig,
+    scene_hash: u64,
+) -> Result<(), CheckpointError> {
+    let io_error = |e| CheckpointError::Io(path.to_path_buf(), e);
+    let header = Header {
+        setup: Setup::new(config, scene_hash),
+        seed: state.seed,
+        passes: state.pass.number,
+        samples: state.pass.samples.clone(),
+        elapsed_seconds: state.pass.elapsed.as_secs_f64(),
+    };
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut w = BufWriter::new(File::create(&tmp).map_err(io_error)?);
+    writeln!(w, "{}", MAGIC).map_err(io_error)?;
+    serde_json::to_writer(&mut w, &header)
+        .map_err(|e| CheckpointError::Io(path.to_path_buf(), e.into()))?;
+    writeln!(w).map_err(io_error)?;
+    state.film.write_pixels(&mut w).map_err(io_error)?;
+    for s in &state.stats {
+        w.write_all(&s.n.to_le_bytes()).map_err(io_error)?;
+        w.write_all(&s.mean.to_le_bytes()).map_err(io_error)?;
+        w.write_all(&s.m2.to_le_bytes()).map_err(io_error)?;
+    }
+    w.flush().map_err(io_error)?;
+    drop(w);
+    fs::rename(&tmp, path).map_err(io_error)
+}
+
+// Reads a checkpoint to carry on rendering with `config`, which may ask for
+// more samples than before but must otherwise match, seed included when it
+// sets one.
+pub fn load(
+    path: &Path,
+    config: &RenderConfig,
+    scene_hash: u64,
+) -> Result<RenderState, CheckpointError> {
+    let format_error = |message: &str| CheckpointError::Format(path.to_path_buf(), message.into());
+    let file = File::open(path).map_err(|e| CheckpointError::Io(path.to_path_buf(), e))?;
+    let mut r = BufReader::new(file);
+
+    let mut line = String::new();
+    r.read_line(&mut line)
+        .map_err(|_| format_error("not a checkpoint file"))?;
+    if line.trim_end() != MAGIC {
+        return Err(format_error("not a checkpoint file"));
+    }
+    line.clear();
+    r.read_line(&mut line)
+        .map_err(|_| format_error("bad header"))?;
+    let header: Header =
+        serde_json::from_str(&line).map_err(|e| format_error(&format!("bad header: {}", e)))?;
+
+    if let Some(difference) = header.setup.difference(&Setup::new(config, scene_hash)) {
+        return Err(CheckpointError::Mismatch(path.to_path_buf(), difference));
+    }
+    // Without a seed of its own the render carries on with the checkpoint's.
+    match config.seed {
+        Some(seed) if seed != header.seed => {
+            return Err(CheckpointError::Mismatch(
+                path.to_path_buf(),
+                format!("it was rendered with seed {}, not {}", header.seed, seed),
+            ));
+        }
+        _ => {}
+    }
+
+    let truncated = |_| format_error("truncated pixel data");
+    let mut film = Film::new(config.img_width, config.img_height, config.filter);
+    film.read_pixels(&mut r).map_err(truncated)?;
+    let mut stats = vec![PixelStats::default(); config.img_width * config.img_height];
+    let mut word = [0u8; 4];
+    for s in &mut stats {
+        r.read_exact(&mut word).map_err(truncated)?;
+        s.n = u32::from_le_bytes(word);
+        r.read_exact(&mut word).map_err(truncated)?;
+        s.mean = f32::from_le_bytes(word);
+        r.read_exact(&mut word).map_err(truncated)?;
+        s.m2 = f32::from_le_bytes(word);
+    }
+
+    Ok(RenderState {
+        film,
+        stats,
+        seed: header.seed,
+        pass: Pass {
+            number: header.passes,
+            samples: header.samples,
+            elapsed: Duration::from_secs_f64(header.elapsed_seconds),
+            tile_times: Vec::new(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hash_files;
+    use super::load;
+    use super::save;
+    use super::CheckpointError;
+    use crate::film::Film;
+    use crate::renderer::Pass;
+    use crate::renderer::PixelStats;
+    use crate::renderer::RenderConfig;
+    use crate::renderer::RenderState;
+    use crate::sampler::SamplerKind;
+    use crate::temp_dir::TempDir;
+    use crate::vec::Color;
+
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dir = TempDir::new("checkpoint");
+        let path = dir.join("render.ckpt");
+        let config = RenderConfig {
+            img_width: 3,
+            img_height: 2,
+            ..Default::default()
+        };
+
+        let mut film = Film::new(3, 2, config.filter);
+        let mut tile = film.tile(0..3, 0..2);
+        tile.add_sample(
+            0.5,
+            0.5,
+            Color {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+            },
+        );
+        tile.add_sample(2.5, 1.5, Default::default());
+        tile.add_sample(2.25, 1.75, Default::default());
+        film.merge_tile(&tile);
+        let mut stats = vec![PixelStats::default(); 6];
+        stats[5] = PixelStats {
+            n: 2,
+            mean: 0.25,
+            m2: 0.125,
+        };
+        let state = RenderState {
+            film,
+            stats,
+            seed: 42,
+            pass: Pass {
+                number: 3,
+                samples: 4..8,
+                elapsed: Duration::from_secs(5),
+                tile_times: Vec::new(),
+            },
+        };
+        save(&path, &state, &config, 7).unwrap();
+
+        let loaded = load(&path, &config, 7).unwrap();
+        assert_eq!(loaded.film.image(), state.film.image());
+        assert_eq!(
+            loaded.film.sample_counts(),
+            vec![vec![1, 0, 0], vec![0, 0, 2]]
+        );
+        let s = loaded.stats[5];
+        assert_eq!((s.n, s.mean, s.m2), (2, 0.25, 0.125));
+        assert_eq!((loaded.seed, loaded.pass.number), (42, 3));
+        assert_eq!(loaded.pass.samples, 4..8);
+        assert_eq!(loaded.pass.elapsed, Duration::from_secs(5));
+
+        // More samples may be asked for, but the scene and settings must match.
+        let more = RenderConfig {
+            number_of_samples: 1000,
+            ..config
+        };
+        assert!(load(&path, &more, 7).is_ok());
+        match load(&path, &config, 8) {
+            Err(CheckpointError::Mismatch(_, message)) => {
+                assert_eq!(message, "the scene or a file it refers to has changed")
+            }
+            _ => panic!("expected a mismatch"),
+        }
+        let deeper = RenderConfig {
+            depth: 50,
+            ..config
+        };
+        match load(&path, &deeper, 7) {
+            Err(CheckpointError::Mismatch(_, message)) => {
+                assert_eq!(message, "it was rendered with depth 30, not 50")
+            }
+            _ => panic!("expected a mismatch"),
+        }
+
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 1]).unwrap();
+        match load(&path, &config, 7) {
+            Err(CheckpointError::Format(_, message)) => {
+                assert_eq!(message, "truncated pixel data")
+            }
+            _ => panic!("expected a format error"),
+        }
+    }
+
+    #[test]
+    fn test_seed_must_match() {
+        let dir = TempDir::new("seed");
+        let path = dir.join("render.ckpt");
+        let config = RenderConfig {
+            img_width: 2,
+            img_height: 2,
+            ..Default::default()
+        };
+        let state = RenderState {
+            film: Film::new(2, 2, config.filter),
+            stats: vec![PixelStats::default(); 4],
+            seed: 5,
+            pass: Pass {
+                number: 1,
+                samples: 0..4,
+                elapsed: Duration::ZERO,
+                tile_times: Vec::new(),
+            },
+        };
+        save(&path, &state, &config, 0).unwrap();
+
+        assert_eq!(load(&path, &config, 0).unwrap().seed, 5);
+        let same = RenderConfig {
+            seed: Some(5),
+            ..config
+        };
+        assert_eq!(load(&path, &same, 0).unwrap().seed, 5);
+        let other = RenderConfig {
+            seed: Some(6),
+            ..config
+        };
+        match load(&path, &other, 0) {
+            Err(CheckpointError::Mismatch(_, message)) => {
+                assert_eq!(message, "it was rendered with seed 5, not 6")
+            }
+            _ => panic!("expected a mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_stratified_total_must_match() {
+        let dir = TempDir::new("strata");
+        let path = dir.join("render.ckpt");
+        let config = RenderConfig {
+            img_width: 2,
+            img_height: 2,
+            number_of_samples: 16,
+            sampler: SamplerKind::Stratified,
+            ..Default::default()
+        };
+        let state = RenderState {
+            film: Film::new(2, 2, config.filter),
+            stats: vec![PixelStats::default(); 4],
+            seed: 1,
+            pass: Pass {
+                number: 1,
+                samples: 0..16,
+                elapsed: Duration::ZERO,
+                tile_times: Vec::new(),
+            },
+        };
+        save(&path, &state, &config, 0).unwrap();
+
+        let more = RenderConfig {
+            number_of_samples: 64,
+            ..config
+        };
+        match load(&path, &more, 0) {
+            Err(CheckpointError::Mismatch(_, message)) => assert_eq!(
+                message,
+                "it was rendered with stratified samples per pixel 16, not 64"
+            ),
+            _ => panic!("expected a mismatch"),
+        }
+        // Other samplers take their samples from a sequence without an end.
+        let sobol = RenderConfig {
+            sampler: SamplerKind::Sobol,
+            ..config
+        };
+        save(&path, &state, &sobol, 0).unwrap();
+        assert!(load(
+            &path,
+            &RenderConfig {
+                number_of_samples: 64,
+                ..sobol
+            },
+            0
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_hash_covers_every_file() {
+        let dir = TempDir::new("hash");
+        let (scene, mesh) = (dir.join("scene.json"), dir.join("mesh.obj"));
+        fs::write(&scene, "{}").unwrap();
+        fs::write(&mesh, "v 0 0 0\n").unwrap();
+        let hash = || hash_files([scene.as_path(), mesh.as_path()]).unwrap();
+        let before = hash();
+        assert_eq!(hash(), before);
+        fs::write(&mesh, "v 0 0 1\n").unwrap();
+        assert_ne!(hash(), before);
+    }
+}
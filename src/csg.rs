@@ -0,0 +1,279 @@
+// Constructive solid geometry on closed shapes, from every intersection of
+// each along the whole line of the ray. Walking the hits of both in order
+// tells which points of the line are inside the combination; its surface is
+// where that changes.
+use crate::aabb::Aabb;
+use crate::error::ValidationError;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::ray::Ray;
+use crate::vec;
+use crate::vec::dot;
+
+use serde::{Deserialize, Serialize};
+
+fn combine(
+    a: &dyn Hittable,
+    b: &dyn Hittable,
+    r: Ray,
+    inside: fn(bool, bool) -> bool,
+) -> Vec<HitRecord> {
+    let mut hits: Vec<(bool, HitRecord)> = a.hit(r).into_iter().map(|h| (false, h)).collect();
+    hits.extend(b.hit(r).into_iter().map(|h| (true, h)));
+    hits.sort_by(|(_, x), (_, y)| x.t.total_cmp(&y.t));
+
+    let mut in_a = false;
+    let mut in_b = false;
+    hits.into_iter()
+        .filter_map(|(from_b, h)| {
+            // Decided by the direction rather than `front_face`, which some
+            // shapes base on the side of the origin, so hits behind it count.
+            let outward = if h.front_face { h.n } else { -h.n };
+            let entering = dot(outward, r.d) < 0.0;
+            let before = inside(in_a, in_b);
+            if from_b {
+                in_b = entering;
+            } else {
+                in_a = entering;
+            }
+            let after = inside(in_a, in_b);
+            (before != after).then(|| HitRecord {
+                n: if dot(h.n, r.d) > 0.0 { -h.n } else { h.n },
+                front_face: after,
+                ..h
+            })
+        })
+        .collect()
+}
+
+fn validate_children(
+    a: &dyn Hittable,
+    b: &dyn Hittable,
+    path: &str,
+    errors: &mut Vec<ValidationError>,
+) {
+    a.validate(&format!("{}.a", path), errors);
+    b.validate(&format!("{}.b", path), errors);
+}
+
+// Points inside either shape.
+#[derive(Serialize, Deserialize)]
+pub struct Union {
+    pub a: Box<dyn Hittable>,
+    pub b: Box<dyn Hittable>,
+}
+
+#[typetag::serde]
+impl Hittable for Union {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        combine(self.a.as_ref(), self.b.as_ref(), r, |a, b| a || b)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::surrounding(
+            self.a.bounding_box()?,
+            self.b.bounding_box()?,
+        ))
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_children(self.a.as_ref(), self.b.as_ref(), path, errors);
+    }
+}
+
+// Points inside both shapes, e.g. a lens from two spheres.
+#[derive(Serialize, Deserialize)]
+pub struct Intersection {
+    pub a: Box<dyn Hittable>,
+    pub b: Box<dyn Hittable>,
+}
+
+#[typetag::serde]
+impl Hittable for Intersection {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        combine(self.a.as_ref(), self.b.as_ref(), r, |a, b| a && b)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match (self.a.bounding_box(), self.b.bounding_box()) {
+            // Built directly, as the boxes may not overlap, in which case it
+            // is empty and never hit.
+            (Some(a), Some(b)) => Some(Aabb {
+                min: vec::max(a.min, b.min),
+                max: vec::min(a.max, b.max),
+            }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_children(self.a.as_ref(), self.b.as_ref(), path, errors);
+    }
+}
+
+// Points inside `a` but not `b`, e.g. a hollow sphere or a drilled hole.
+#[derive(Serialize, Deserialize)]
+pub struct Difference {
+    pub a: Box<dyn Hittable>,
+    pub b: Box<dyn Hittable>,
+}
+
+#[typetag::serde]
+impl Hittable for Difference {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        combine(self.a.as_ref(), self.b.as_ref(), r, |a, b| a && !b)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.a.bounding_box()
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_children(self.a.as_ref(), self.b.as_ref(), path, errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Difference;
+    use super::Intersection;
+    use super::Union;
+    use crate::hittable::Hittable;
+    use crate::quad::Cuboid;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::vec::Point;
+    use crate::vec::Vec3;
+
+    fn sphere(x: f32, r: f32) -> Box<Sphere> {
+        Box::new(Sphere {
+            o: Point { x, y: 0.0, z: 0.0 },
+            r,
+        })
+    }
+
+    // The (t, front_face) of every hit of a ray along x from x = -10.
+    fn hits(h: &dyn Hittable) -> Vec<(f32, bool)> {
+        let r = Ray {
+            origin: Point {
+                x: -10.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            d: Vec3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        };
+        let mut hits: Vec<(f32, bool)> = h.hit(r).iter().map(|h| (h.t, h.front_face)).collect();
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+        hits
+    }
+
+    #[test]
+    fn test_csg_intervals() {
+        let lens = Intersection {
+            a: sphere(-1.5, 2.0),
+            b: sphere(1.5, 2.0),
+        };
+        assert_eq!(hits(&lens), [(9.5, true), (10.5, false)]);
+
+        let hollow = Difference {
+            a: sphere(0.0, 2.0),
+            b: sphere(0.0, 1.0),
+        };
+        assert_eq!(
+            hits(&hollow),
+            [(8.0, true), (9.0, false), (11.0, true), (12.0, false)]
+        );
+
+        // From the hollow's center, the first surface is the inside of its
+        // shell, which the ray enters.
+        let r = Ray {
+            origin: Default::default(),
+            d: Vec3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        };
+        let h = hollow.closest_hit(r, 0.0, f32::MAX).unwrap();
+        assert_eq!((h.t, h.front_face), (1.0, true));
+        assert_eq!(h.n.x, -1.0);
+
+        // Nothing is left of a shape minus a larger one.
+        let nothing = Difference {
+            a: sphere(0.0, 1.0),
+            b: sphere(0.0, 2.0),
+        };
+        assert_eq!(hits(&nothing), []);
+    }
+
+    #[test]
+    fn test_ray_through_box_edge() {
+        let cuboid = Box::new(Cuboid {
+            min: Point {
+                x: -1.0,
+                y: -1.0,
+                z: -1.0,
+            },
+            max: Point {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+        });
+        // Grazes the edge at x = y = -1, where it meets two sides of the box
+        // going opposite ways, then passes through the sphere.
+        let union = Union {
+            a: cuboid,
+            b: Box::new(Sphere {
+                o: Point {
+                    x: -4.0,
+                    y: 2.0,
+                    z: 0.0,
+                },
+                r: 1.0,
+            }),
+        };
+        let r = Ray {
+            origin: Point {
+                x: 1.0,
+                y: -3.0,
+                z: 0.0,
+            },
+            d: Vec3 {
+                x: -1.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        };
+        let mut hits: Vec<(f32, bool)> = union.hit(r).iter().map(|h| (h.t, h.front_face)).collect();
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let faces: Vec<bool> = hits.iter().map(|h| h.1).collect();
+        assert_eq!(faces, [true, false, true, false]);
+        assert_eq!((hits[0].0, hits[1].0), (2.0, 2.0));
+        let half_chord = 1.0 / 2f32.sqrt();
+        assert!((hits[2].0 - (5.0 - half_chord)).abs() < 1e-5);
+        assert!((hits[3].0 - (5.0 + half_chord)).abs() < 1e-5);
+
+        // Through the edge into the box, the way in and out is found once.
+        let r = Ray {
+            origin: Point {
+                x: -3.0,
+                y: -3.0,
+                z: 0.0,
+            },
+            d: Vec3 {
+                x: 1.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        };
+        let h = union.a.hit(r);
+        assert_eq!(h.len(), 2);
+        assert_eq!((h[0].t, h[0].front_face), (2.0, true));
+        assert_eq!((h[1].t, h[1].front_face), (4.0, false));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::checkpoint::CheckpointError;
+use crate::hdr::HdrError;
+use crate::image::ImageError;
+use crate::obj::ObjError;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+// A semantic problem with an otherwise well-formed input, located by the
+// JSON path of the offending field, e.g. `world.objects[3].hittable.r`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub location: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(location: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            location: location.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Json {
+        path: PathBuf,
+        // Path of the field that failed to parse, "." or "?" when unknown.
+        field: String,
+        source: serde_json::Error,
+    },
+    Obj(ObjError),
+    Hdr(HdrError),
+    Image(ImageError),
+    Checkpoint(CheckpointError),
+    // Stopped by the user before finishing.
+    Interrupted,
+    Invalid {
+        // None when the input came from the command line.
+        path: Option<PathBuf>,
+        errors: Vec<ValidationError>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // Exit status following the BSD sysexits conventions.
+    pub fn exit_code(&self) -> u8 {
+        const EX_USAGE: u8 = 64;
+        const EX_DATAERR: u8 = 65;
+        const EX_NOINPUT: u8 = 66;
+        const EX_CANTCREAT: u8 = 73;
+        const EX_IOERR: u8 = 74;
+        // What a shell reports for a process killed by SIGINT.
+        const INTERRUPTED: u8 = 130;
+
+        match self {
+            Error::Io { source, .. } if source.kind() == io::ErrorKind::NotFound => EX_NOINPUT,
+            Error::Io { .. } => EX_IOERR,
+            Error::Obj(ObjError::Io(..)) | Error::Hdr(HdrError::Io(..)) => EX_NOINPUT,
+            Error::Checkpoint(CheckpointError::Io(_, e)) if e.kind() == io::ErrorKind::NotFound => {
+                EX_NOINPUT
+            }
+            Error::Checkpoint(CheckpointError::Io(..)) => EX_IOERR,
+            Error::Image(ImageError::UnsupportedFormat(_)) => EX_USAGE,
+            Error::Image(ImageError::Io(_)) => EX_CANTCREAT,
+            Error::Image(_) => EX_IOERR,
+            Error::Interrupted => INTERRUPTED,
+            Error::Json { .. }
+            | Error::Obj(_)
+            | Error::Hdr(_)
+            | Error::Checkpoint(_)
+            | Error::Invalid { .. } => EX_DATAERR,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Json {
+                path,
+                field,
+                source,
+            } => {
+                write!(
+                    f,
+                    "{}:{}:{}: ",
+                    path.display(),
+                    source.line(),
+                    source.column()
+                )?;
+                if field != "." && field != "?" {
+                    write!(f, "at `{}`: ", field)?;
+                }
+                // serde_json appends its own position, which is already in front.
+                let message = source.to_string();
+                let message = match message.rfind(" at line ") {
+                    Some(i) if source.line() > 0 => &message[..i],
+                    _ => &message,
+                };
+                write!(f, "{}", message)
+            }
+            Error::Obj(e) => write!(f, "{}", e),
+            Error::Hdr(e) => write!(f, "{}", e),
+            Error::Image(e) => write!(f, "{}", e),
+            Error::Checkpoint(e) => write!(f, "{}", e),
+            Error::Interrupted => write!(f, "interrupted"),
+            Error::Invalid { path, errors } => {
+                if let Some(path) = path {
+                    write!(f, "{}: ", path.display())?;
+                }
+                write!(f, "{} problem(s) found", errors.len())?;
+                for e in errors {
+                    write!(f, "\n  {}", e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            Error::Obj(e) => Some(e),
+            Error::Hdr(e) => Some(e),
+            Error::Image(e) => Some(e),
+            Error::Checkpoint(e) => Some(e),
+            Error::Interrupted | Error::Invalid { .. } => None,
+        }
+    }
+}
+
+impl From<ObjError> for Error {
+    fn from(e: ObjError) -> Self {
+        Error::Obj(e)
+    }
+}
+
+impl From<HdrError> for Error {
+    fn from(e: HdrError) -> Self {
+        Error::Hdr(e)
+    }
+}
+
+impl From<CheckpointError> for Error {
+    fn from(e: CheckpointError) -> Self {
+        Error::Checkpoint(e)
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(e: ImageError) -> Self {
+        Error::Image(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use super::ValidationError;
+    use crate::checkpoint::CheckpointError;
+    use crate::image::ImageError;
+    use crate::obj::ObjError;
+
+    use std::io;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_exit_codes() {
+        let path = PathBuf::from("scene.json");
+        let io_error = |kind| Error::Io {
+            path: path.clone(),
+            source: io::Error::from(kind),
+        };
+        let cases = [
+            (io_error(io::ErrorKind::NotFound), 66),
+            (io_error(io::ErrorKind::PermissionDenied), 74),
+            (
+                Error::Obj(ObjError::Io(
+                    path.clone(),
+                    io::Error::from(io::ErrorKind::NotFound),
+                )),
+                66,
+            ),
+            (
+                Error::Obj(ObjError::Parse {
+                    path: path.clone(),
+                    line: 1,
+                    message: String::new(),
+                }),
+                65,
+            ),
+            (
+                Error::Checkpoint(CheckpointError::Mismatch(path.clone(), String::new())),
+                65,
+            ),
+            (
+                Error::Image(ImageError::UnsupportedFormat("tga".to_string())),
+                64,
+            ),
+            (
+                Error::Invalid {
+                    path: None,
+                    errors: vec![ValidationError::new("depth", "must be positive")],
+                },
+                65,
+            ),
+            (Error::Interrupted, 130),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{}", error);
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use crate::error::ValidationError;
+use crate::vec::Color;
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::io;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::str::FromStr;
+
+// Pixel reconstruction filter. Each sample is added to every pixel whose
+// center lies within `radius` pixels of it on both axes, weighted by the
+// product of the 1D filter in x and y.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Filter {
+    Box(BoxFilter),
+    Tent(TentFilter),
+    Gaussian(GaussianFilter),
+    Mitchell(MitchellFilter),
+    Lanczos(LanczosFilter),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BoxFilter {
+    pub radius: f32,
+}
+
+impl Default for BoxFilter {
+    fn default() -> Self {
+        Self { radius: 0.5 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TentFilter {
+    pub radius: f32,
+}
+
+impl Default for TentFilter {
+    fn default() -> Self {
+        Self { radius: 1.0 }
+    }
+}
+
+// Shifted down so it reaches zero at the radius.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GaussianFilter {
+    pub radius: f32,
+    pub sigma: f32,
+}
+
+impl Default for GaussianFilter {
+    fn default() -> Self {
+        Self {
+            radius: 1.5,
+            sigma: 0.5,
+        }
+    }
+}
+
+// Mitchell and Netravali, "Reconstruction Filters in Computer Graphics".
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MitchellFilter {
+    pub radius: f32,
+    pub b: f32,
+    pub c: f32,
+}
+
+impl Default for MitchellFilter {
+    fn default() -> Self {
+        Self {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }
+    }
+}
+
+// Sinc windowed by a sinc stretched to the radius.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanczosFilter {
+    pub radius: f32,
+}
+
+impl Default for LanczosFilter {
+    fn default() -> Self {
+        Self { radius: 3.0 }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box(Default::default())
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "box" => Ok(Filter::Box(Default::default())),
+            "tent" => Ok(Filter::Tent(Default::default())),
+            "gaussian" => Ok(Filter::Gaussian(Default::default())),
+            "mitchell" => Ok(Filter::Mitchell(Default::default())),
+            "lanczos" => Ok(Filter::Lanczos(Default::default())),
+            _ => Err(format!(
+                "unknown filter '{}', expected one of box, tent, gaussian, mitchell, lanczos",
+                s
+            )),
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+impl Filter {
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box(BoxFilter { radius })
+            | Filter::Tent(TentFilter { radius })
+            | Filter::Gaussian(GaussianFilter { radius, .. })
+            | Filter::Mitchell(MitchellFilter { radius, .. })
+            | Filter::Lanczos(LanczosFilter { radius }) => radius,
+        }
+    }
+
+    pub fn set_radius(&mut self, r: f32) {
+        match self {
+            Filter::Box(BoxFilter { radius })
+            | Filter::Tent(TentFilter { radius })
+            | Filter::Gaussian(GaussianFilter { radius, .. })
+            | Filter::Mitchell(MitchellFilter { radius, .. })
+            | Filter::Lanczos(LanczosFilter { radius }) => *radius = r,
+        }
+    }
+
+    fn eval_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match *self {
+            Filter::Box(_) => 1.0,
+            Filter::Tent(TentFilter { radius }) => (radius - x).max(0.0),
+            Filter::Gaussian(GaussianFilter { radius, sigma }) => {
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell(MitchellFilter { radius, b, c }) => {
+                // The standard filter spans [-2, 2].
+                let x = 2.0 * x / radius;
+                if x > 1.0 {
+                    ((-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos(LanczosFilter { radius }) => sinc(x) * sinc(x / radius),
+        }
+    }
+
+    pub fn eval(&self, dx: f32, dy: f32) -> f32 {
+        self.eval_1d(dx) * self.eval_1d(dy)
+    }
+
+    pub fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if !(self.radius() > 0.0 && self.radius().is_finite()) {
+            errors.push(ValidationError::new(
+                format!("{}.radius", path),
+                "must be positive",
+            ));
+        }
+        if let Filter::Gaussian(GaussianFilter { sigma, .. }) = *self {
+            if !(sigma > 0.0 && sigma.is_finite()) {
+                errors.push(ValidationError::new(
+                    format!("{}.sigma", path),
+                    "must be positive",
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct FilmPixel {
+    weighted_sum: Color,
+    weight: f32,
+    // Samples taken inside this pixel, whichever pixels they were added to.
+    samples: u32,
+}
+
+// Weighted sums of the samples around each pixel, rows top to bottom, in
+// continuous coordinates where pixel (x, y) covers [x, x + 1) x [y, y + 1).
+pub struct Film {
+    width: usize,
+    height: usize,
+    filter: Filter,
+    pixels: Vec<FilmPixel>,
+}
+
+// The part of a film one worker writes to, merged back in afterwards so
+// threads never share pixels.
+pub struct FilmTile {
+    x: Range<usize>,
+    y: Range<usize>,
+    filter: Filter,
+    pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: Filter) -> Self {
+        Self {
+            width,
+            height,
+            filter,
+            pixels: vec![Default::default(); width * height],
+        }
+    }
+
+    // A tile for rendering the pixels in the given ranges, padded by the
+    // filter's reach so it also holds their samples' share of neighbours.
+    pub fn tile(&self, x: Range<usize>, y: Range<usize>) -> FilmTile {
+        let reach = (self.filter.radius() - 0.5).ceil().max(0.0) as usize;
+        let x = x.start.saturating_sub(reach)..(x.end + reach).min(self.width);
+        let y = y.start.saturating_sub(reach)..(y.end + reach).min(self.height);
+        FilmTile {
+            pixels: vec![Default::default(); x.len() * y.len()],
+            x,
+            y,
+            filter: self.filter,
+        }
+    }
+
+    pub fn merge_tile(&mut self, tile: &FilmTile) {
+        for (y, row) in tile.y.clone().zip(tile.pixels.chunks(tile.x.len())) {
+            for (x, p) in tile.x.clone().zip(row) {
+                let pixel = &mut self.pixels[y * self.width + x];
+                pixel.weighted_sum = pixel.weighted_sum + p.weighted_sum;
+                pixel.weight += p.weight;
+                pixel.samples += p.samples;
+            }
+        }
+    }
+
+    pub fn image(&self) -> Vec<Vec<Color>> {
+        self.pixels
+            .chunks(self.width)
+            .map(|row| {
+                row.iter()
+                    .map(|p| match p.weight {
+                        w if w != 0.0 => p.weighted_sum / w,
+                        _ => Default::default(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Raw pixel sums, little-endian, for checkpoints.
+    pub(crate) fn write_pixels<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for p in &self.pixels {
+            for x in [
+                p.weighted_sum.x,
+                p.weighted_sum.y,
+                p.weighted_sum.z,
+                p.weight,
+            ] {
+                w.write_all(&x.to_le_bytes())?;
+            }
+            w.write_all(&p.samples.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_pixels<R: Read>(&mut self, r: &mut R) -> io::Result<()> {
+        let mut word = [0u8; 4];
+        let mut next = |r: &mut R| r.read_exact(&mut word).map(|_| word);
+        for p in &mut self.pixels {
+            p.weighted_sum.x = f32::from_le_bytes(next(r)?);
+            p.weighted_sum.y = f32::from_le_bytes(next(r)?);
+            p.weighted_sum.z = f32::from_le_bytes(next(r)?);
+            p.weight = f32::from_le_bytes(next(r)?);
+            p.samples = u32::from_le_bytes(next(r)?);
+        }
+        Ok(())
+    }
+
+    pub fn sample_counts(&self) -> Vec<Vec<u32>> {
+        self.pixels
+            .chunks(self.width)
+            .map(|row| row.iter().map(|p| p.samples).collect())
+            .collect()
+    }
+
+    // Sample counts as colors running from blue for none through cyan, green
+    // and yellow to red for `max_samples`.
+    pub fn sample_heatmap(&self, max_samples: u32) -> Vec<Vec<Color>> {
+        let ramp = [
+            Color {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            },
+            Color {
+                x: 0.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            Color {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            Color {
+                x: 1.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            Color {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        ];
+        let color = |samples: u32| {
+            let t = (samples as f32 / max_samples.max(1) as f32).clamp(0.0, 1.0);
+            let t = t * (ramp.len() - 1) as f32;
+            let i = (t as usize).min(ramp.len() - 2);
+            ramp[i] * (1.0 - (t - i as f32)) + ramp[i + 1] * (t - i as f32)
+        };
+        self.sample_counts()
+            .into_iter()
+            .map(|row| row.into_iter().map(color).collect())
+            .collect()
+    }
+}
+
+impl FilmTile {
+    pub fn add_sample(&mut self, px: f32, py: f32, color: Color) {
+        let radius = self.filter.radius();
+        // Pixels whose centers, at x + 0.5, are in (p - radius, p + radius],
+        // so a sample on the edge of two pixels only counts once under a box.
+        let pixels = |p: f32, range: &Range<usize>| {
+            let clamp = |x: f32| x.clamp(range.start as f32, range.end as f32) as usize;
+            clamp((p - 0.5 - radius).floor() + 1.0)..clamp((p - 0.5 + radius).floor() + 1.0)
+        };
+
+        let width = self.x.len();
+        let (x, y) = (px as usize, py as usize);
+        if self.x.contains(&x) && self.y.contains(&y) {
+            self.pixels[(y - self.y.start) * width + x - self.x.start].samples += 1;
+        }
+        for y in pixels(py, &self.y) {
+            for x in pixels(px, &self.x) {
+                let weight = self
+                    .filter
+                    .eval(px - (x as f32 + 0.5), py - (y as f32 + 0.5));
+                let pixel = &mut self.pixels[(y - self.y.start) * width + x - self.x.start];
+                pixel.weighted_sum = pixel.weighted_sum + color * weight;
+                pixel.weight += weight;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Film;
+    use super::Filter;
+    use super::GaussianFilter;
+    use crate::vec::Color;
+
+    #[test]
+    fn test_box_filter_keeps_samples_in_their_pixel() {
+        let mut film = Film::new(3, 1, Filter::default());
+        let mut tile = film.tile(1..2, 0..1);
+        let color = Color {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+        };
+        tile.add_sample(1.0, 0.5, color);
+        tile.add_sample(1.99, 0.5, color);
+        film.merge_tile(&tile);
+
+        let image = film.image();
+        assert_eq!(image[0][0], Color::default());
+        assert_eq!(image[0][1], color);
+        assert_eq!(image[0][2], Color::default());
+        assert_eq!(film.sample_counts(), vec![vec![0, 2, 0]]);
+    }
+
+    #[test]
+    fn test_filter_parameters_default_when_left_out() {
+        let filter: Filter =
+            serde_json::from_str(r#"{"type": "Gaussian", "sigma": 0.25}"#).unwrap();
+        assert_eq!(
+            filter,
+            Filter::Gaussian(GaussianFilter {
+                radius: 1.5,
+                sigma: 0.25
+            })
+        );
+        let filter: Filter = serde_json::from_str(r#"{"type": "Box"}"#).unwrap();
+        assert_eq!(filter, Filter::default());
+        assert_eq!(
+            serde_json::to_value(filter).unwrap(),
+            serde_json::json!({"type": "Box", "radius": 0.5})
+        );
+    }
+}
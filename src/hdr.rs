@@ -0,0 +1,254 @@
+use crate::vec::Color;
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum HdrError {
+    Io(PathBuf, io::Error),
+    Format(PathBuf, String),
+}
+
+impl fmt::Display for HdrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HdrError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            HdrError::Format(path, message) => {
+                write!(
+                    f,
+                    "{}: invalid Radiance HDR file: {}",
+                    path.display(),
+                    message
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for HdrError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            HdrError::Io(_, e) => Some(e),
+            HdrError::Format(..) => None,
+        }
+    }
+}
+
+// Linear RGB image, rows stored top to bottom.
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl HdrImage {
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    // Bilinear lookup with u wrapping around and v clamped, both in [0, 1].
+    pub fn sample(&self, u: f32, v: f32) -> Color {
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let wrap = |x: f32| (x as i64).rem_euclid(self.width as i64) as usize;
+        let (x0, x1) = (wrap(x0), wrap(x0 + 1.0));
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(self.height - 1);
+
+        (self.get(x0, y0) * (1.0 - fx) + self.get(x1, y0) * fx) * (1.0 - fy)
+            + (self.get(x0, y1) * (1.0 - fx) + self.get(x1, y1) * fx) * fy
+    }
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Default::default();
+    }
+    let f = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+    Color {
+        x: (rgbe[0] as f32 + 0.5) * f,
+        y: (rgbe[1] as f32 + 0.5) * f,
+        z: (rgbe[2] as f32 + 0.5) * f,
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let b = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| "unexpected end of file".to_string())?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn line(&mut self) -> Result<&str, String> {
+        let rest = &self.data[self.pos..];
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| "unterminated header".to_string())?;
+        self.pos += end + 1;
+        std::str::from_utf8(&rest[..end]).map_err(|_| "header is not text".to_string())
+    }
+}
+
+// Reads one scanline, either flat or in the run-length encoding newer writers use.
+fn read_scanline(reader: &mut Reader, width: usize) -> Result<Vec<Color>, String> {
+    let first = [
+        reader.byte()?,
+        reader.byte()?,
+        reader.byte()?,
+        reader.byte()?,
+    ];
+    let is_rle =
+        (8..0x8000).contains(&width) && first[0] == 2 && first[1] == 2 && first[2] & 0x80 == 0;
+    if !is_rle {
+        let mut line = vec![rgbe_to_color(first)];
+        for _ in 1..width {
+            line.push(rgbe_to_color([
+                reader.byte()?,
+                reader.byte()?,
+                reader.byte()?,
+                reader.byte()?,
+            ]));
+        }
+        return Ok(line);
+    }
+
+    if ((first[2] as usize) << 8 | first[3] as usize) != width {
+        return Err("scanline width mismatch".to_string());
+    }
+    let mut channels = vec![[0u8; 4]; width];
+    for c in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = reader.byte()? as usize;
+            let (run, count) = if count > 128 {
+                (true, count - 128)
+            } else {
+                (false, count)
+            };
+            if count == 0 || x + count > width {
+                return Err("bad scanline run length".to_string());
+            }
+            if run {
+                let value = reader.byte()?;
+                channels[x..x + count].iter_mut().for_each(|p| p[c] = value);
+            } else {
+                for p in &mut channels[x..x + count] {
+                    p[c] = reader.byte()?;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(channels.into_iter().map(rgbe_to_color).collect())
+}
+
+// Larger than any real environment map, so corrupt headers are refused
+// before allocating for them.
+const MAX_PIXELS: usize = 1 << 28;
+
+fn parse_hdr(data: &[u8]) -> Result<HdrImage, String> {
+    let mut reader = Reader { data, pos: 0 };
+    let magic = reader.line()?;
+    if !magic.starts_with("#?") {
+        return Err("missing #? signature".to_string());
+    }
+    loop {
+        let line = reader.line()?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported format {}", format));
+            }
+        }
+    }
+
+    // Only the standard top-to-bottom, left-to-right orientation is supported.
+    let resolution: Vec<&str> = reader.line()?.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", h, "+X", w] => (
+            h.parse::<usize>().map_err(|_| "bad height".to_string())?,
+            w.parse::<usize>().map_err(|_| "bad width".to_string())?,
+        ),
+        _ => return Err("unsupported resolution line".to_string()),
+    };
+    if width == 0 || height == 0 {
+        return Err("empty image".to_string());
+    }
+    let size = width
+        .checked_mul(height)
+        .filter(|&n| n <= MAX_PIXELS)
+        .ok_or_else(|| format!("image of {}x{} pixels is too large", width, height))?;
+
+    let mut pixels = Vec::with_capacity(size);
+    for _ in 0..height {
+        pixels.extend(read_scanline(&mut reader, width)?);
+    }
+    Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+pub fn load_hdr(path: &Path) -> Result<HdrImage, HdrError> {
+    let data = fs::read(path).map_err(|e| HdrError::Io(path.to_path_buf(), e))?;
+    parse_hdr(&data).map_err(|message| HdrError::Format(path.to_path_buf(), message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_hdr;
+    use crate::vec::Color;
+
+    #[test]
+    fn test_parse_rle_scanline() {
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        data.extend([2, 2, 0, 8]);
+        // Red: a run of 8. Green: 8 literal bytes. Blue and exponent: runs.
+        data.extend([128 + 8, 128]);
+        data.extend([8, 0, 0, 0, 0, 128, 128, 128, 128]);
+        data.extend([128 + 8, 0]);
+        data.extend([128 + 8, 129]);
+
+        let image = parse_hdr(&data).unwrap();
+        assert_eq!((image.width, image.height), (8, 1));
+        let f = 1.0 / 128.0;
+        assert_eq!(
+            image.get(0, 0),
+            Color {
+                x: 128.5 * f,
+                y: 0.5 * f,
+                z: 0.5 * f,
+            }
+        );
+        assert_eq!(image.get(7, 0).y, 128.5 * f);
+    }
+
+    #[test]
+    fn test_reject_oversized_header() {
+        for (h, w) in [("100000", "100000"), ("18446744073709551615", "2")] {
+            let data = format!("#?RADIANCE\n\n-Y {} +X {}\n", h, w);
+            assert_eq!(
+                parse_hdr(data.as_bytes()).err(),
+                Some(format!("image of {}x{} pixels is too large", w, h))
+            );
+        }
+    }
+}
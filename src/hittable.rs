@@ -1,11 +1,23 @@
-use crate::rand;
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::error::ValidationError;
+use crate::instance::Group;
+use crate::instance::Instance;
+use crate::instance::Library;
+use crate::instance::PlacedInstance;
+use crate::obj;
+use crate::obj::ObjError;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::dot;
 use crate::vec::Color;
 use crate::vec::Point;
 use crate::vec::Vec3;
 
 use std::borrow::Borrow;
+use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use serde::{Deserialize, Serialize};
 
@@ -26,17 +38,106 @@ pub struct WorldHitRecord<'a> {
 
 #[typetag::serde(tag = "type")]
 pub trait Hittable: Sync {
+    // Every intersection along the ray, in no particular order.
     fn hit(&self, r: Ray) -> Vec<HitRecord>;
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.hit(r)
+            .into_iter()
+            .filter(|h| t_min <= h.t && h.t <= t_max)
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+    }
+    // None for unbounded shapes, which are tested outside of any BVH.
+    fn bounding_box(&self) -> Option<Aabb>;
+    // Samples a point on the surface as seen from `origin`, for light sampling.
+    fn sample(&self, _origin: Point, _sampler: &mut Sampler) -> Option<LightSample> {
+        None
+    }
+    // Solid angle density with which `sample` would have produced the hit `h`.
+    fn sample_pdf(&self, _origin: Point, _h: HitRecord) -> f32 {
+        0.0
+    }
+    // Reports problems that would make rendering fail or produce NaNs, with
+    // locations under `path`, the JSON path of this value.
+    fn validate(&self, _path: &str, _errors: &mut Vec<ValidationError>) {}
+}
+
+pub fn validate_point(p: Point, path: String, errors: &mut Vec<ValidationError>) {
+    if !p.is_finite() {
+        errors.push(ValidationError::new(path, "must be finite"));
+    }
+}
+
+pub fn validate_color(c: Color, path: String, errors: &mut Vec<ValidationError>) {
+    if !c.is_finite() || c.x < 0.0 || c.y < 0.0 || c.z < 0.0 {
+        errors.push(ValidationError::new(
+            path,
+            "must be finite and non-negative",
+        ));
+    }
+}
+
+pub struct LightSample {
+    // Hit record of the ray from the origin to the sampled point, which lies at t = 1.
+    pub hit_record: HitRecord,
+    pub pdf: f32,
+}
+
+impl LightSample {
+    pub fn new(origin: Point, p: Point, outward_n: Vec3, pdf: f32) -> Self {
+        let front_face = dot(outward_n, origin - p) >= 0.0;
+        Self {
+            hit_record: HitRecord {
+                t: 1.0,
+                p,
+                n: if front_face { outward_n } else { -outward_n },
+                front_face,
+            },
+            pdf,
+        }
+    }
+
+    // For shapes sampled uniformly by area, converting the density to solid angle.
+    pub fn from_area(origin: Point, p: Point, outward_n: Vec3, area: f32) -> Option<Self> {
+        let pdf = area_to_solid_angle_pdf(origin, p, outward_n, area);
+        (pdf > 0.0 && pdf.is_finite()).then(|| Self::new(origin, p, outward_n, pdf))
+    }
+}
+
+pub fn area_to_solid_angle_pdf(origin: Point, p: Point, n: Vec3, area: f32) -> f32 {
+    let d = p - origin;
+    let cos = dot(n, d).abs() / d.length();
+    if cos <= 0.0 {
+        return 0.0;
+    }
+    d.length_squared() / (cos * area)
 }
 
 pub struct ScatterResult {
     pub attenuation: Color,
     pub scattered_ray: Ray,
+    // Solid angle density of the scattered direction; None for specular
+    // scattering, which light sampling cannot reproduce.
+    pub pdf: Option<f32>,
 }
 
 #[typetag::serde(tag = "type")]
 pub trait Material: Sync {
-    fn scatter(&self, r: Ray, h: HitRecord) -> Option<ScatterResult>;
+    fn scatter(&self, r: Ray, h: HitRecord, sampler: &mut Sampler) -> Option<ScatterResult>;
+    fn emitted(&self, _r: Ray, _h: HitRecord) -> Color {
+        Default::default()
+    }
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    // BSDF times the cosine term for light arriving from direction `wi`.
+    fn eval(&self, _r: Ray, _h: HitRecord, _wi: Vec3) -> Color {
+        Default::default()
+    }
+    // Density with which `scatter` would have picked `wi`.
+    fn pdf(&self, _r: Ray, _h: HitRecord, _wi: Vec3) -> f32 {
+        0.0
+    }
+    fn validate(&self, _path: &str, _errors: &mut Vec<ValidationError>) {}
 }
 
 #[derive(Serialize, Deserialize)]
@@ -46,21 +147,35 @@ pub struct Lambertian {
 
 #[typetag::serde]
 impl Material for Lambertian {
-    fn scatter(&self, _: Ray, h: HitRecord) -> Option<ScatterResult> {
-        let mut new_ray = Ray {
+    fn scatter(&self, r: Ray, h: HitRecord, sampler: &mut Sampler) -> Option<ScatterResult> {
+        let new_ray = Ray {
             origin: h.p + 1e-3 * h.n,
-            d: rand::random_in_hemisphere(h.n),
+            d: sampler.in_hemisphere(h.n),
         };
 
-        if new_ray.d.length() < 1e-8 {
-            new_ray.d = h.n;
-        }
-
         Some(ScatterResult {
             attenuation: self.albedo,
             scattered_ray: new_ray,
+            pdf: Some(self.pdf(r, h, new_ray.d)),
         })
     }
+
+    // `scatter` picks directions uniformly over the hemisphere and weights
+    // them by the albedo, so the BSDF times the cosine is the albedo over 2π.
+    fn eval(&self, r: Ray, h: HitRecord, wi: Vec3) -> Color {
+        self.albedo * self.pdf(r, h, wi)
+    }
+
+    fn pdf(&self, _: Ray, h: HitRecord, wi: Vec3) -> f32 {
+        match dot(wi, h.n) > 0.0 {
+            true => 1.0 / (2.0 * PI),
+            _ => 0.0,
+        }
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_color(self.albedo, format!("{}.albedo", path), errors);
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -75,18 +190,29 @@ fn reflect_vector(v: Vec3, n: Vec3) -> Vec3 {
 
 #[typetag::serde]
 impl Material for Metal {
-    fn scatter(&self, r: Ray, h: HitRecord) -> Option<ScatterResult> {
+    fn scatter(&self, r: Ray, h: HitRecord, sampler: &mut Sampler) -> Option<ScatterResult> {
         let reflected_d = reflect_vector(r.d, h.n);
         let new_ray = Ray {
             origin: h.p + 1e-3 * h.n,
-            d: reflected_d + self.fuzziness * rand::random_in_sphere(),
+            d: reflected_d + self.fuzziness * sampler.in_sphere(),
         };
 
         Some(ScatterResult {
             attenuation: self.albedo,
             scattered_ray: new_ray,
+            pdf: None,
         })
     }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_color(self.albedo, format!("{}.albedo", path), errors);
+        if !(self.fuzziness >= 0.0 && self.fuzziness.is_finite()) {
+            errors.push(ValidationError::new(
+                format!("{}.fuzziness", path),
+                "must be finite and non-negative",
+            ));
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -96,7 +222,7 @@ pub struct Dielectric {
 
 #[typetag::serde]
 impl Material for Dielectric {
-    fn scatter(&self, r: Ray, h: HitRecord) -> Option<ScatterResult> {
+    fn scatter(&self, r: Ray, h: HitRecord, _: &mut Sampler) -> Option<ScatterResult> {
         let unit_d = r.d.unit();
         let refraction_rate = if h.front_face { 1.0 / self.ir } else { self.ir };
         let cos_theta = dot(-unit_d, h.n).min(1.0);
@@ -122,8 +248,55 @@ impl Material for Dielectric {
                 z: 1.0,
             },
             scattered_ray: Ray { origin, d },
+            pdf: None,
         })
     }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if !(self.ir > 0.0 && self.ir.is_finite()) {
+            errors.push(ValidationError::new(
+                format!("{}.ir", path),
+                "refractive index must be positive",
+            ));
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DiffuseLight {
+    pub color: Color,
+    pub intensity: f32,
+    #[serde(default)]
+    pub two_sided: bool,
+}
+
+#[typetag::serde]
+impl Material for DiffuseLight {
+    fn scatter(&self, _: Ray, _: HitRecord, _: &mut Sampler) -> Option<ScatterResult> {
+        None
+    }
+
+    fn emitted(&self, _: Ray, h: HitRecord) -> Color {
+        if h.front_face || self.two_sided {
+            self.color * self.intensity
+        } else {
+            Default::default()
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_color(self.color, format!("{}.color", path), errors);
+        if !(self.intensity >= 0.0 && self.intensity.is_finite()) {
+            errors.push(ValidationError::new(
+                format!("{}.intensity", path),
+                "must be finite and non-negative",
+            ));
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -137,6 +310,29 @@ impl Hittable for Object {
     fn hit(&self, r: Ray) -> Vec<HitRecord> {
         self.hittable.hit(r)
     }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.hittable.closest_hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.hittable.bounding_box()
+    }
+
+    fn sample(&self, origin: Point, sampler: &mut Sampler) -> Option<LightSample> {
+        self.hittable.sample(origin, sampler)
+    }
+
+    fn sample_pdf(&self, origin: Point, h: HitRecord) -> f32 {
+        self.hittable.sample_pdf(origin, h)
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        self.material
+            .validate(&format!("{}.material", path), errors);
+        self.hittable
+            .validate(&format!("{}.hittable", path), errors);
+    }
 }
 
 pub fn get_closest_hit_in_range<'a>(
@@ -156,30 +352,274 @@ pub fn get_closest_hit_in_range<'a>(
         .copied()
 }
 
+// A BVH over the bounded ones of a list of hittables, with the unbounded
+// ones tested separately.
+pub(crate) struct SceneBvh {
+    bvh: Bvh,
+    unbounded: Vec<usize>,
+}
+
+impl SceneBvh {
+    pub(crate) fn new(boxes: impl Iterator<Item = Option<Aabb>>) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        for (i, bbox) in boxes.enumerate() {
+            match bbox {
+                Some(bbox) => bounded.push((i, bbox)),
+                None => unbounded.push(i),
+            }
+        }
+        Self {
+            bvh: Bvh::new(bounded),
+            unbounded,
+        }
+    }
+
+    // Calls `f` with every index the ray may hit.
+    pub(crate) fn for_each_candidate<F: FnMut(usize)>(&self, r: Ray, mut f: F) {
+        self.bvh.for_each_candidate(r, 0.0, f32::MAX, &mut f);
+        self.unbounded.iter().for_each(|&i| f(i));
+    }
+
+    // Like `Bvh::closest_hit`, starting with the unbounded hittables.
+    pub(crate) fn closest_hit<T, F>(
+        &self,
+        r: Ray,
+        t_min: f32,
+        mut t_max: f32,
+        mut f: F,
+    ) -> Option<T>
+    where
+        F: FnMut(usize, f32) -> Option<(f32, T)>,
+    {
+        let mut closest = None;
+        for &i in &self.unbounded {
+            if let Some((t, payload)) = f(i, t_max) {
+                t_max = t;
+                closest = Some(payload);
+            }
+        }
+        self.bvh.closest_hit(r, t_min, t_max, f).or(closest)
+    }
+}
+
+// A Wavefront OBJ file to import into the world, resolved relative to the
+// scene file. The optional material replaces the ones from its MTL files.
+#[derive(Serialize, Deserialize)]
+pub struct MeshImport {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<Box<dyn Material>>,
+}
+
+// Objects own their shapes and materials. Shapes placed many times are
+// defined once under `geometry`, with materials from `materials`, and placed
+// by `instances`; those are hit but not sampled as lights.
 #[derive(Default, Serialize, Deserialize)]
 pub struct World {
     objects: Vec<Object>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    meshes: Vec<MeshImport>,
+    #[serde(default, skip_serializing_if = "Library::is_empty")]
+    materials: Library<Box<dyn Material>>,
+    #[serde(default, skip_serializing_if = "Library::is_empty")]
+    geometry: Library<Group>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    instances: Vec<Instance>,
+    #[serde(skip)]
+    placed: OnceLock<Vec<PlacedInstance>>,
+    // Over the objects, then the placed instances.
+    #[serde(skip)]
+    bvh: OnceLock<SceneBvh>,
+    #[serde(skip)]
+    lights: OnceLock<Vec<usize>>,
 }
 
 impl World {
     pub fn add_object(&mut self, object: Object) {
         self.objects.push(object);
+        self.bvh.take();
+        self.lights.take();
     }
 
-    pub fn hit(&self, r: Ray) -> Vec<WorldHitRecord> {
-        let mut hits = Vec::<WorldHitRecord>::new();
+    pub fn add_material(&mut self, name: &str, material: Box<dyn Material>) {
+        self.materials.insert(name, material);
+        self.placed.take();
+        self.bvh.take();
+    }
+
+    pub fn add_geometry(&mut self, name: &str, group: Group) {
+        self.geometry.insert(name, group);
+        self.placed.take();
+        self.bvh.take();
+    }
+
+    pub fn add_instance(&mut self, instance: Instance) {
+        self.instances.push(instance);
+        self.placed.take();
+        self.bvh.take();
+    }
+
+    pub fn object(&self, id: u32) -> &Object {
+        &self.objects[id as usize]
+    }
+
+    // Ids of the objects with emissive materials.
+    pub fn lights(&self) -> &[usize] {
+        self.lights.get_or_init(|| {
+            (0..self.objects.len())
+                .filter(|&i| self.objects[i].material.is_emissive())
+                .collect()
+        })
+    }
+
+    // Picks a light uniformly and samples a point on it, folding the choice into the pdf.
+    pub fn sample_light(&self, origin: Point, sampler: &mut Sampler) -> Option<(u32, LightSample)> {
+        let lights = self.lights();
+        if lights.is_empty() {
+            return None;
+        }
+        let i = ((sampler.get_1d() * lights.len() as f32) as usize).min(lights.len() - 1);
+        let id = lights[i];
+        self.objects[id].sample(origin, sampler).map(|mut s| {
+            s.pdf /= lights.len() as f32;
+            (id as u32, s)
+        })
+    }
+
+    // Density with which `sample_light` would have produced the hit `h` on object `id`.
+    pub fn light_pdf(&self, id: u32, origin: Point, h: HitRecord) -> f32 {
+        if id as usize >= self.objects.len() {
+            return 0.0;
+        }
+        let object = self.object(id);
+        if !object.material.is_emissive() {
+            return 0.0;
+        }
+        object.sample_pdf(origin, h) / self.lights().len() as f32
+    }
+
+    pub fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
         for (i, object) in self.objects.iter().enumerate() {
-            let mut obj_hits = object
-                .hit(r)
-                .into_iter()
-                .map(|h| WorldHitRecord {
-                    hit_record: h,
-                    material: object.material.borrow(),
-                    object_id: i as u32,
-                })
-                .collect();
-            hits.append(&mut obj_hits);
+            object.validate(&format!("{}.objects[{}]", path, i), errors);
+        }
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            if let Some(material) = &mesh.material {
+                material.validate(&format!("{}.meshes[{}].material", path, i), errors);
+            }
+        }
+        for (name, material) in self.materials.iter() {
+            material.validate(&format!("{}.materials.{}", path, name), errors);
+        }
+        for (name, group) in self.geometry.iter() {
+            group.validate(
+                &format!("{}.geometry.{}", path, name),
+                &self.materials,
+                errors,
+            );
+        }
+        for (i, instance) in self.instances.iter().enumerate() {
+            instance.validate(
+                &format!("{}.instances[{}]", path, i),
+                &self.geometry,
+                &self.materials,
+                errors,
+            );
+        }
+    }
+
+    pub fn add_mesh(&mut self, mesh: MeshImport) {
+        self.meshes.push(mesh);
+    }
+
+    // Replaces the pending mesh imports with the objects they describe,
+    // adding the paths of the files read to `files`.
+    pub fn load_meshes(
+        &mut self,
+        base_dir: &Path,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<(), ObjError> {
+        for mesh in std::mem::take(&mut self.meshes) {
+            for object in obj::load_obj(&base_dir.join(&mesh.path), mesh.material, files)? {
+                self.add_object(object);
+            }
         }
+        Ok(())
+    }
+
+    fn placed(&self) -> &[PlacedInstance] {
+        self.placed.get_or_init(|| {
+            self.instances
+                .iter()
+                .filter_map(|instance| instance.place(&self.geometry, &self.materials))
+                .collect()
+        })
+    }
+
+    fn bvh(&self) -> &SceneBvh {
+        self.bvh.get_or_init(|| {
+            let objects = self.objects.iter().map(|o| o.bounding_box());
+            let instances = self.placed().iter().map(|p| {
+                let group = self.geometry.get(p.group);
+                Some(p.matrices.bounding_box_to_world(group.bounding_box()?))
+            });
+            SceneBvh::new(objects.chain(instances))
+        })
+    }
+
+    fn world_hit_record(&self, id: usize, part: usize, h: HitRecord) -> WorldHitRecord<'_> {
+        let material = match id.checked_sub(self.objects.len()) {
+            None => self.objects[id].material.borrow(),
+            Some(k) => self
+                .materials
+                .get(self.placed()[k].materials[part])
+                .borrow(),
+        };
+        WorldHitRecord {
+            hit_record: h,
+            material,
+            object_id: id as u32,
+        }
+    }
+
+    pub fn hit(&self, r: Ray) -> Vec<WorldHitRecord<'_>> {
+        let mut hits = Vec::<WorldHitRecord>::new();
+        self.bvh()
+            .for_each_candidate(r, |i| match i.checked_sub(self.objects.len()) {
+                None => hits.extend(
+                    self.objects[i]
+                        .hit(r)
+                        .into_iter()
+                        .map(|h| self.world_hit_record(i, 0, h)),
+                ),
+                Some(k) => {
+                    let placed = &self.placed()[k];
+                    let group = self.geometry.get(placed.group);
+                    hits.extend(group.hit(placed.matrices.ray_to_object(r)).into_iter().map(
+                        |(part, h)| {
+                            self.world_hit_record(i, part, placed.matrices.hit_to_world(r, h))
+                        },
+                    ));
+                }
+            });
         hits
     }
+
+    pub fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<WorldHitRecord<'_>> {
+        self.bvh().closest_hit(r, t_min, t_max, |i, t_max| {
+            let (part, h) = match i.checked_sub(self.objects.len()) {
+                None => (0, self.objects[i].closest_hit(r, t_min, t_max)?),
+                Some(k) => {
+                    let placed = &self.placed()[k];
+                    let (part, h) = self.geometry.get(placed.group).closest_hit(
+                        placed.matrices.ray_to_object(r),
+                        t_min,
+                        t_max,
+                    )?;
+                    (part, placed.matrices.hit_to_world(r, h))
+                }
+            };
+            Some((h.t, self.world_hit_record(i, part, h)))
+        })
+    }
 }
@@ -0,0 +1,146 @@
+use crate::tonemap::PostProcessConfig;
+use crate::vec::Color;
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    Png(png::EncodingError),
+    Exr(exr::error::Error),
+    UnsupportedFormat(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{}", e),
+            ImageError::Png(e) => write!(f, "PNG encoding failed: {}", e),
+            ImageError::Exr(e) => write!(f, "EXR encoding failed: {}", e),
+            ImageError::UnsupportedFormat(ext) => write!(
+                f,
+                "unsupported image format '{}', expected one of png, exr, pfm, ppm",
+                ext
+            ),
+        }
+    }
+}
+
+impl error::Error for ImageError {}
+
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> Self {
+        ImageError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for ImageError {
+    fn from(e: png::EncodingError) -> Self {
+        ImageError::Png(e)
+    }
+}
+
+impl From<exr::error::Error> for ImageError {
+    fn from(e: exr::error::Error) -> Self {
+        ImageError::Exr(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Exr,
+    Pfm,
+    Ppm,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Result<Self, ImageError> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        match ext.as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "exr" => Ok(ImageFormat::Exr),
+            "pfm" => Ok(ImageFormat::Pfm),
+            "ppm" => Ok(ImageFormat::Ppm),
+            _ => Err(ImageError::UnsupportedFormat(ext)),
+        }
+    }
+}
+
+// Rows are stored top to bottom, each pixel holding linear radiance.
+fn rgb8_pixels(img: &[Vec<Color>], post: &PostProcessConfig) -> Vec<u8> {
+    img.iter()
+        .flatten()
+        .flat_map(|&c| post.to_rgb8(c))
+        .collect()
+}
+
+fn write_png(path: &Path, img: &[Vec<Color>], post: &PostProcessConfig) -> Result<(), ImageError> {
+    let w = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(w, img[0].len() as u32, img.len() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    if post.gamma.is_none() {
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rgb8_pixels(img, post))?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_ppm(path: &Path, img: &[Vec<Color>], post: &PostProcessConfig) -> Result<(), ImageError> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write!(w, "P6\n{} {}\n255\n", img[0].len(), img.len())?;
+    w.write_all(&rgb8_pixels(img, post))?;
+    w.flush()?;
+    Ok(())
+}
+
+fn write_exr(path: &Path, img: &[Vec<Color>], post: &PostProcessConfig) -> Result<(), ImageError> {
+    exr::prelude::write_rgb_file(path, img[0].len(), img.len(), |x, y| {
+        let c = post.expose(img[y][x]);
+        (c.x, c.y, c.z)
+    })?;
+    Ok(())
+}
+
+// PFM stores rows bottom to top; a negative scale marks little-endian data.
+fn write_pfm(path: &Path, img: &[Vec<Color>], post: &PostProcessConfig) -> Result<(), ImageError> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write!(w, "PF\n{} {}\n-1.0\n", img[0].len(), img.len())?;
+    for row in img.iter().rev() {
+        for &c in row {
+            let c = post.expose(c);
+            for v in [c.x, c.y, c.z] {
+                w.write_all(&v.to_le_bytes())?;
+            }
+        }
+    }
+    w.flush()?;
+    Ok(())
+}
+
+// HDR formats keep linear values and only get the exposure applied, while
+// 8-bit formats go through the full tone mapping pipeline.
+pub fn write_image(
+    path: &Path,
+    img: &[Vec<Color>],
+    post: &PostProcessConfig,
+) -> Result<(), ImageError> {
+    match ImageFormat::from_path(path)? {
+        ImageFormat::Png => write_png(path, img, post),
+        ImageFormat::Exr => write_exr(path, img, post),
+        ImageFormat::Pfm => write_pfm(path, img, post),
+        ImageFormat::Ppm => write_ppm(path, img, post),
+    }
+}
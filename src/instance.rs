@@ -0,0 +1,355 @@
+use crate::aabb::Aabb;
+use crate::error::ValidationError;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::hittable::Material;
+use crate::hittable::SceneBvh;
+use crate::ray::Ray;
+use crate::transform::Matrices;
+use crate::transform::TransformStep;
+
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::OnceLock;
+
+// Named definitions, a JSON object in the scene file, kept in the order they
+// were given so they can be referred to by index once looked up.
+pub struct Library<T> {
+    entries: Vec<(String, T)>,
+}
+
+impl<T> Default for Library<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T> Library<T> {
+    // Replaces any definition with the same name.
+    pub fn insert(&mut self, name: &str, item: T) {
+        match self.index_of(name) {
+            Some(i) => self.entries[i].1 = item,
+            None => self.entries.push((name.to_string(), item)),
+        }
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|(n, _)| n == name)
+    }
+
+    pub fn get(&self, i: usize) -> &T {
+        &self.entries[i].1
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.entries.iter().map(|(n, item)| (n.as_str(), item))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T: Serialize> Serialize for Library<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.entries.iter().map(|(n, item)| (n, item)))
+    }
+}
+
+struct LibraryVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> de::Visitor<'de> for LibraryVisitor<T> {
+    type Value = Library<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of names to definitions")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut library = Library::default();
+        while let Some((name, item)) = map.next_entry::<String, T>()? {
+            if library.index_of(&name).is_some() {
+                return Err(de::Error::custom(format!("'{}' is defined twice", name)));
+            }
+            library.entries.push((name, item));
+        }
+        Ok(library)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Library<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(LibraryVisitor(PhantomData))
+    }
+}
+
+fn unknown_material(name: &str, path: String, errors: &mut Vec<ValidationError>) {
+    errors.push(ValidationError::new(
+        path,
+        format!("no material named '{}'", name),
+    ));
+}
+
+// A piece of a group, with the name of its material in the world's library.
+#[derive(Serialize, Deserialize)]
+pub struct Part {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    pub hittable: Box<dyn Hittable>,
+}
+
+// Geometry defined once and placed by instances, with its own BVH so each
+// instance is a single entry in the world's.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Group {
+    pub parts: Vec<Part>,
+    #[serde(skip)]
+    bvh: OnceLock<SceneBvh>,
+}
+
+impl Group {
+    pub fn new(parts: Vec<Part>) -> Self {
+        Self {
+            parts,
+            bvh: OnceLock::new(),
+        }
+    }
+
+    fn bvh(&self) -> &SceneBvh {
+        self.bvh
+            .get_or_init(|| SceneBvh::new(self.parts.iter().map(|p| p.hittable.bounding_box())))
+    }
+
+    // None if any part is unbounded.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.parts
+            .iter()
+            .map(|p| p.hittable.bounding_box())
+            .reduce(|a, b| Some(Aabb::surrounding(a?, b?)))
+            .flatten()
+    }
+
+    // Every hit, with the index of the part it is on.
+    pub fn hit(&self, r: Ray) -> Vec<(usize, HitRecord)> {
+        let mut hits = Vec::new();
+        self.bvh().for_each_candidate(r, |i| {
+            hits.extend(self.parts[i].hittable.hit(r).into_iter().map(|h| (i, h)));
+        });
+        hits
+    }
+
+    pub fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<(usize, HitRecord)> {
+        self.bvh().closest_hit(r, t_min, t_max, |i, t_max| {
+            self.parts[i]
+                .hittable
+                .closest_hit(r, t_min, t_max)
+                .map(|h| (h.t, (i, h)))
+        })
+    }
+
+    pub fn validate(
+        &self,
+        path: &str,
+        materials: &Library<Box<dyn Material>>,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        if self.parts.is_empty() {
+            errors.push(ValidationError::new(
+                format!("{}.parts", path),
+                "must not be empty",
+            ));
+        }
+        for (i, part) in self.parts.iter().enumerate() {
+            let path = format!("{}.parts[{}]", path, i);
+            if let Some(name) = &part.material {
+                if materials.index_of(name).is_none() {
+                    unknown_material(name, format!("{}.material", path), errors);
+                }
+            }
+            part.hittable
+                .validate(&format!("{}.hittable", path), errors);
+        }
+    }
+}
+
+// A placement of a named group. The material, if given, replaces those of
+// all its parts.
+#[derive(Serialize, Deserialize)]
+pub struct Instance {
+    pub geometry: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<TransformStep>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+}
+
+// An instance with its names looked up.
+pub(crate) struct PlacedInstance {
+    pub(crate) group: usize,
+    // Index in the material library for each part of the group.
+    pub(crate) materials: Vec<usize>,
+    pub(crate) matrices: Matrices,
+}
+
+impl Instance {
+    // None if a name is missing, which validate reports.
+    pub(crate) fn place(
+        &self,
+        geometry: &Library<Group>,
+        materials: &Library<Box<dyn Material>>,
+    ) -> Option<PlacedInstance> {
+        let group = geometry.index_of(&self.geometry)?;
+        let material = |part: &Part| {
+            let name = self.material.as_ref().or(part.material.as_ref())?;
+            materials.index_of(name)
+        };
+        Some(PlacedInstance {
+            group,
+            materials: geometry
+                .get(group)
+                .parts
+                .iter()
+                .map(material)
+                .collect::<Option<_>>()?,
+            matrices: Matrices::new(&self.steps),
+        })
+    }
+
+    pub fn validate(
+        &self,
+        path: &str,
+        geometry: &Library<Group>,
+        materials: &Library<Box<dyn Material>>,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        for (i, step) in self.steps.iter().enumerate() {
+            step.validate(&format!("{}.steps[{}]", path, i), errors);
+        }
+        if let Some(name) = &self.material {
+            if materials.index_of(name).is_none() {
+                unknown_material(name, format!("{}.material", path), errors);
+            }
+        }
+        match geometry.index_of(&self.geometry) {
+            None => errors.push(ValidationError::new(
+                format!("{}.geometry", path),
+                format!("no geometry named '{}'", self.geometry),
+            )),
+            Some(i) => {
+                let unmaterialed = geometry.get(i).parts.iter().any(|p| p.material.is_none());
+                if self.material.is_none() && unmaterialed {
+                    errors.push(ValidationError::new(
+                        format!("{}.material", path),
+                        format!("'{}' has parts without a material", self.geometry),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Group;
+    use super::Instance;
+    use super::Part;
+    use crate::hittable::Lambertian;
+    use crate::hittable::World;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::vec::Point;
+    use crate::vec::Vec3;
+
+    #[test]
+    fn test_instances_share_geometry() {
+        let world: World = serde_json::from_str(
+            r#"{
+                "objects": [],
+                "materials": {
+                    "white": {"type": "Lambertian", "albedo": {"x": 1, "y": 1, "z": 1}},
+                    "light": {"type": "DiffuseLight", "color": {"x": 1, "y": 1, "z": 1}, "intensity": 1}
+                },
+                "geometry": {
+                    "ball": {"parts": [{"material": "white", "hittable": {"type": "Sphere", "o": {"x": 0, "y": 0, "z": 0}, "r": 1}}]}
+                },
+                "instances": [
+                    {"geometry": "ball", "steps": [{"translate": {"x": 0, "y": 0, "z": -5}}]},
+                    {"geometry": "ball", "steps": [{"scale": 2}, {"translate": {"x": 0, "y": 0, "z": -20}}], "material": "light"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut errors = Vec::new();
+        world.validate("world", &mut errors);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let ray = |x: f32| Ray {
+            origin: Point { x, y: 0.0, z: 0.0 },
+            d: Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -1.0,
+            },
+        };
+        let h = world.closest_hit(ray(0.0), 0.0, f32::MAX).unwrap();
+        assert!((h.hit_record.t - 4.0).abs() < 1e-4);
+        assert!(!h.material.is_emissive());
+
+        // Past the first ball, onto the second one's larger, overridden surface.
+        let h = world.closest_hit(ray(1.5), 0.0, f32::MAX).unwrap();
+        assert!((h.hit_record.t - (20.0 - 1.75f32.sqrt())).abs() < 1e-4);
+        assert!(h.material.is_emissive());
+        assert_eq!(world.hit(ray(0.0)).len(), 4);
+    }
+
+    #[test]
+    fn test_material_added_after_first_hit() {
+        let mut world = World::default();
+        world.add_geometry(
+            "ball",
+            Group::new(vec![Part {
+                material: Some("white".to_string()),
+                hittable: Box::new(Sphere {
+                    o: Point {
+                        x: 0.0,
+                        y: 0.0,
+                        z: -5.0,
+                    },
+                    r: 1.0,
+                }),
+            }]),
+        );
+        world.add_instance(Instance {
+            geometry: "ball".to_string(),
+            steps: Vec::new(),
+            material: None,
+        });
+        let r = Ray {
+            origin: Default::default(),
+            d: Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -1.0,
+            },
+        };
+        // Without its material the instance cannot be placed yet.
+        assert!(world.closest_hit(r, 0.0, f32::MAX).is_none());
+
+        world.add_material(
+            "white",
+            Box::new(Lambertian {
+                albedo: Vec3 {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 1.0,
+                },
+            }),
+        );
+        let h = world.closest_hit(r, 0.0, f32::MAX).unwrap();
+        assert!((h.hit_record.t - 4.0).abs() < 1e-4);
+    }
+}
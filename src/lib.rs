@@ -0,0 +1,30 @@
+pub mod aabb;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod csg;
+pub mod error;
+pub mod film;
+pub mod hdr;
+pub mod hittable;
+pub mod image;
+pub mod instance;
+pub mod mat;
+pub mod obj;
+pub mod plane;
+pub mod quad;
+pub mod rand;
+pub mod ray;
+pub mod renderer;
+pub mod revolution;
+pub mod sampler;
+pub mod scene;
+pub mod sphere;
+#[cfg(test)]
+mod temp_dir;
+pub mod tile;
+pub mod tonemap;
+pub mod transform;
+pub mod triangle;
+pub mod vec;
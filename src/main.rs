@@ -1,115 +1,403 @@
-use raytracing::camera;
-use raytracing::hittable::get_closest_hit_in_range;
-use raytracing::hittable::World;
-use raytracing::rand;
-use raytracing::ray::Ray;
-use raytracing::scene;
-use raytracing::vec::Color;
-
-use rayon::prelude::*;
-use serde::de::DeserializeOwned;
-use serde::{Deserialize, Serialize};
-
-use std::env;
+use raytracing::checkpoint;
+use raytracing::error::Error;
+use raytracing::error::Result;
+use raytracing::film::Filter;
+use raytracing::image;
+use raytracing::renderer::AdaptiveSampling;
+use raytracing::renderer::RenderConfig;
+use raytracing::renderer::RenderState;
+use raytracing::renderer::Renderer;
+use raytracing::sampler::SamplerKind;
+use raytracing::scene::Scene;
+use raytracing::tile::Tile;
+use raytracing::tile::TileOrder;
+use raytracing::tonemap::ToneMapOperator;
+
+use clap::Parser;
+
 use std::fs::File;
-use std::io::BufReader;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::io;
+use std::io::{BufWriter, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Renders a JSON scene description to an image.
+///
+/// Render settings start from built-in defaults, with the image shaped like
+/// the scene's camera, are replaced by the values in the --config file if one
+/// is given, and then by any individual flags.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Cli {
+    /// Scene description, as written by scene_generator
+    #[arg(short, long)]
+    scene: PathBuf,
+
+    /// Render settings file; any field it leaves out keeps its default
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Output image; the format is picked from the extension (png, exr, pfm, ppm)
+    #[arg(short, long, required_unless_present = "check")]
+    output: Option<PathBuf>,
+
+    /// Only load and validate the scene and settings, without rendering
+    #[arg(long)]
+    check: bool,
+
+    /// Image width in pixels; the height follows the aspect ratio unless also given
+    #[arg(long)]
+    width: Option<usize>,
+
+    /// Image height in pixels; the width follows the aspect ratio unless also given
+    #[arg(long)]
+    height: Option<usize>,
+
+    /// Samples per pixel
+    #[arg(long)]
+    samples: Option<u32>,
+
+    /// Maximum number of bounces per path
+    #[arg(long)]
+    depth: Option<u32>,
+
+    /// Fewest samples per pixel; enables adaptive sampling
+    #[arg(long)]
+    min_samples: Option<u32>,
+
+    /// Most samples per pixel; enables adaptive sampling
+    #[arg(long)]
+    max_samples: Option<u32>,
+
+    /// Relative noise at which a pixel stops sampling; enables adaptive sampling
+    #[arg(long)]
+    noise_threshold: Option<f32>,
+
+    /// Also write an image of how many samples each pixel took
+    #[arg(long)]
+    sample_heatmap: Option<PathBuf>,
+
+    /// Stop after this many seconds, keeping the samples taken so far
+    #[arg(long)]
+    time_limit: Option<f32>,
+
+    /// Write the image so far to the output every this many seconds
+    #[arg(long)]
+    snapshot_interval: Option<f32>,
+
+    /// Write the image so far to the output every this many passes
+    #[arg(long)]
+    snapshot_passes: Option<u32>,
+
+    /// Size in pixels of the square tiles work is split into
+    #[arg(long)]
+    tile_size: Option<usize>,
+
+    /// Order tiles are rendered in: scanline, spiral or hilbert
+    #[arg(long)]
+    tile_order: Option<TileOrder>,
+
+    /// Write the time spent on each tile to this CSV file
+    #[arg(long)]
+    tile_stats: Option<PathBuf>,
+
+    /// Save progress here periodically, on Ctrl-C and at the end
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoints
+    #[arg(long, default_value_t = 300.0)]
+    checkpoint_interval: f32,
+
+    /// Carry on from a checkpoint, adding samples up to the current settings;
+    /// progress is saved back to it unless --checkpoint says otherwise
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// Seed for reproducible renders
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Sample sequence: random, stratified, halton, sobol or blue-noise
+    #[arg(long)]
+    sampler: Option<SamplerKind>,
+
+    /// Pixel filter: box, tent, gaussian, mitchell or lanczos
+    #[arg(long)]
+    filter: Option<Filter>,
+
+    /// Filter radius in pixels; each filter has its own default
+    #[arg(long)]
+    filter_radius: Option<f32>,
+
+    /// Exposure in stops
+    #[arg(long)]
+    exposure: Option<f32>,
+
+    /// Tone mapping operator: clamp, reinhard or aces
+    #[arg(long)]
+    tone_mapping: Option<ToneMapOperator>,
+
+    /// Number of worker threads; defaults to one per core
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+}
+
+impl Cli {
+    fn render_config(&self, scene: &Scene) -> Result<RenderConfig> {
+        let mut config = match &self.config {
+            Some(path) => RenderConfig::from_file(path)?,
+            None => {
+                let config = RenderConfig::default();
+                let aspect_ratio = scene.camera_config.aspect_ratio;
+                RenderConfig {
+                    aspect_ratio,
+                    img_height: (config.img_width as f32 / aspect_ratio).round() as usize,
+                    ..config
+                }
+            }
+        };
+
+        match (self.width, self.height) {
+            (Some(w), Some(h)) => {
+                config.img_width = w;
+                config.img_height = h;
+                config.aspect_ratio = w as f32 / h as f32;
+            }
+            (Some(w), None) => {
+                config.img_width = w;
+                config.img_height = (w as f32 / config.aspect_ratio).round() as usize;
+            }
+            (None, Some(h)) => {
+                config.img_height = h;
+                config.img_width = (h as f32 * config.aspect_ratio).round() as usize;
+            }
+            (None, None) => {}
+        }
+        if let Some(samples) = self.samples {
+            config.number_of_samples = samples;
+        }
+        if self.min_samples.is_some()
+            || self.max_samples.is_some()
+            || self.noise_threshold.is_some()
+        {
+            let adaptive = config
+                .adaptive
+                .get_or_insert_with(AdaptiveSampling::default);
+            if let Some(min_samples) = self.min_samples {
+                adaptive.min_samples = min_samples;
+            }
+            if let Some(max_samples) = self.max_samples {
+                adaptive.max_samples = max_samples;
+            }
+            if let Some(noise_threshold) = self.noise_threshold {
+                adaptive.noise_threshold = noise_threshold;
+            }
+        }
+        if let Some(depth) = self.depth {
+            config.depth = depth;
+        }
+        if let Some(tile_size) = self.tile_size {
+            config.tile_size = tile_size;
+        }
+        if let Some(tile_order) = self.tile_order {
+            config.tile_order = tile_order;
+        }
+        if self.time_limit.is_some() {
+            config.time_limit = self.time_limit;
+        }
+        if self.seed.is_some() {
+            config.seed = self.seed;
+        }
+        if let Some(sampler) = self.sampler {
+            config.sampler = sampler;
+        }
+        if let Some(filter) = self.filter {
+            config.filter = filter;
+        }
+        if let Some(radius) = self.filter_radius {
+            config.filter.set_radius(radius);
+        }
+        if let Some(exposure) = self.exposure {
+            config.post_process.exposure = exposure;
+        }
+        if let Some(tone_mapping) = self.tone_mapping {
+            config.post_process.tone_mapping = tone_mapping;
+        }
 
-fn ppm_print(img: &[Vec<Color>]) {
-    println!("P3\n{} {}\n255", img[0].len(), img.len());
-    for row in img {
-        for pixel_color in row {
-            println!("{}", pixel_color);
+        let errors = config.validate();
+        if !errors.is_empty() {
+            return Err(Error::Invalid {
+                path: self.config.clone(),
+                errors,
+            });
         }
+        Ok(config)
     }
 }
 
-fn ray_color_blue_gradient(r: Ray) -> Color {
-    let u = r.d.unit();
-    let t = (u.y + 1.0) / 2.0;
-    Color {
-        x: 1.0,
-        y: 1.0,
-        z: 1.0,
-    } * (1.0 - t)
-        + Color {
-            x: 0.5,
-            y: 0.7,
-            z: 1.0,
-        } * t
+// One line per tile with its position and the seconds spent on it.
+fn write_tile_stats(path: &Path, tiles: &[Tile], times: &[Duration]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "x,y,width,height,seconds")?;
+    for (tile, time) in tiles.iter().zip(times) {
+        writeln!(
+            w,
+            "{},{},{},{},{}",
+            tile.x.start,
+            tile.y.start,
+            tile.x.len(),
+            tile.y.len(),
+            time.as_secs_f64()
+        )?;
+    }
+    w.flush()
 }
 
-fn ray_color(r: Ray, w: &World, depth: u32) -> Color {
-    if depth == 0 {
-        return Color {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-        };
+fn run(cli: &Cli) -> Result<()> {
+    let scene = Scene::from_file(&cli.scene)?;
+    let render_config = cli.render_config(&scene)?;
+    let errors = render_config.validate_for(&scene);
+    if !errors.is_empty() {
+        return Err(Error::Invalid {
+            path: Some(cli.scene.clone()),
+            errors,
+        });
     }
 
-    match get_closest_hit_in_range(&w.hit(r), 0.0001, f32::MAX) {
-        None => ray_color_blue_gradient(r),
-        Some(h) => match h.material.scatter(r, h.hit_record) {
-            Some(s) => ray_color(s.scattered_ray, w, depth - 1) * s.attenuation,
-            None => Default::default(),
-        },
+    let output = match (&cli.output, cli.check) {
+        (Some(output), false) => output,
+        _ => {
+            eprintln!("{}: ok", cli.scene.display());
+            return Ok(());
+        }
+    };
+    image::ImageFormat::from_path(output)?;
+    if let Some(heatmap) = &cli.sample_heatmap {
+        image::ImageFormat::from_path(heatmap)?;
     }
-}
 
-#[derive(Serialize, Deserialize, Debug)]
-struct RenderConfig {
-    aspect_ratio: f32,
-    img_width: usize,
-    img_height: usize,
-    number_of_samples: u32,
-    depth: u32,
-}
+    let checkpoint_path = cli.checkpoint.as_ref().or(cli.resume.as_ref());
+    let scene_files = iter::once(&cli.scene).chain(scene.assets());
+    let scene_hash = match checkpoint_path {
+        Some(_) => checkpoint::hash_files(scene_files.map(PathBuf::as_path))?,
+        None => 0,
+    };
+    let resume = match &cli.resume {
+        Some(path) => Some(checkpoint::load(path, &render_config, scene_hash)?),
+        None => None,
+    };
 
-fn deserialize_from_file<T: DeserializeOwned>(path: &str) -> T {
-    let file = File::open(path).expect("Failed to open file");
-    let buf_reader = BufReader::new(file);
-    serde_json::from_reader(buf_reader).expect("Failed to deserialize")
-}
+    // The first Ctrl-C stops after saving what has been done; a second one
+    // exits straight away.
+    static CANCEL: AtomicBool = AtomicBool::new(false);
+    let handler = ctrlc::set_handler(|| {
+        if CANCEL.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!("Interrupted, finishing up..");
+    });
+    if let Err(e) = handler {
+        eprintln!("warning: could not install Ctrl-C handler: {}", e);
+    }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let scene: scene::Scene = deserialize_from_file(args[1].as_str());
-    let render_config: RenderConfig = deserialize_from_file(args[2].as_str());
-
-    let mut img = vec![
-        vec![
-            Color {
-                x: 0.0,
-                y: 0.0,
-                z: 0.0
-            };
-            render_config.img_width
-        ];
-        render_config.img_height
-    ];
-
-    let cam = camera::Camera::new(scene.camera_config);
-    let w = scene.world;
-
-    let c_rows = AtomicUsize::new(0);
-    img.par_iter_mut().enumerate().for_each(|(i, row)| {
-        for (j, cell) in row.iter_mut().enumerate() {
-            let (r, c) = ((render_config.img_height - i - 1) as f32, j as f32);
-
-            for _ in 0..render_config.number_of_samples {
-                let u = (r + rand::get_random_offset()) / (render_config.img_height - 1) as f32;
-                let v = (c + rand::get_random_offset()) / (render_config.img_width - 1) as f32;
-                *cell = *cell + ray_color(cam.get_ray(u, v), &w, render_config.depth);
+    let renderer = Renderer::new(scene, render_config);
+    let config = renderer.config();
+    let post = &config.post_process;
+    let save_checkpoint = |state: &RenderState| -> Result<()> {
+        if let Some(path) = checkpoint_path {
+            checkpoint::save(path, state, config, scene_hash)?;
+        }
+        Ok(())
+    };
+    let mut last_snapshot = (0, Instant::now());
+    let mut last_checkpoint = Instant::now();
+    let mut tile_times = vec![Duration::ZERO; renderer.tiles().len()];
+    let state = renderer.render_progressive(resume, &CANCEL, |state| {
+        let (film, pass) = (&state.film, &state.pass);
+        let slowest = pass.tile_times.iter().max().copied().unwrap_or_default();
+        let mean = pass.tile_times.iter().sum::<Duration>() / pass.tile_times.len().max(1) as u32;
+        eprintln!(
+            "Pass {}: {} samples per pixel, {:.1}s, tiles take {:.1}ms on average, {:.1}ms at most",
+            pass.number,
+            pass.samples.end,
+            pass.elapsed.as_secs_f32(),
+            mean.as_secs_f32() * 1000.0,
+            slowest.as_secs_f32() * 1000.0
+        );
+        for (total, time) in tile_times.iter_mut().zip(&pass.tile_times) {
+            *total += *time;
+        }
+        let due_by_time = cli
+            .snapshot_interval
+            .is_some_and(|s| last_snapshot.1.elapsed().as_secs_f32() >= s);
+        let due_by_passes = cli
+            .snapshot_passes
+            .is_some_and(|n| pass.number - last_snapshot.0 >= n);
+        if due_by_time || due_by_passes {
+            // A failed snapshot is not worth losing the render over.
+            if let Err(e) = image::write_image(output, &film.image(), post) {
+                eprintln!("warning: could not write snapshot: {}", e);
             }
-
-            *cell = *cell / (render_config.number_of_samples as f32);
+            last_snapshot = (pass.number, Instant::now());
+        }
+        if last_checkpoint.elapsed().as_secs_f32() >= cli.checkpoint_interval {
+            if let Err(e) = save_checkpoint(state) {
+                eprintln!("warning: could not save checkpoint: {}", e);
+            }
+            last_checkpoint = Instant::now();
         }
-        let rows_done = c_rows.fetch_add(1, Ordering::SeqCst);
-        eprintln!("Rows remaining {}", render_config.img_height - rows_done);
     });
+    let film = &state.film;
+    save_checkpoint(&state)?;
+    if let Some(path) = checkpoint_path {
+        eprintln!("Saved checkpoint to {}", path.display());
+    }
+
+    if let Some(path) = &cli.tile_stats {
+        write_tile_stats(path, renderer.tiles(), &tile_times).map_err(|source| Error::Io {
+            path: path.clone(),
+            source,
+        })?;
+    }
 
-    eprintln!("Printing image..");
-    ppm_print(&img);
+    eprintln!("Writing image to {}..", output.display());
+    image::write_image(output, &film.image(), post)?;
+    if let Some(heatmap) = &cli.sample_heatmap {
+        let (_, max_samples) = config.sample_range();
+        image::write_image(
+            heatmap,
+            &film.sample_heatmap(max_samples),
+            &Default::default(),
+        )?;
+    }
+    if CANCEL.load(Ordering::SeqCst) {
+        return Err(Error::Interrupted);
+    }
     eprintln!("Done!");
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Some(threads) = cli.threads {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            eprintln!("error: failed to start worker threads: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
 }
@@ -0,0 +1,140 @@
+use crate::vec::Point;
+use crate::vec::Vec3;
+
+use std::ops;
+
+// Row-major 4x4 matrix acting on column vectors, for affine transforms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Self { m }
+    }
+
+    pub fn translate(t: Vec3) -> Self {
+        let mut r = Self::identity();
+        r.m[0][3] = t.x;
+        r.m[1][3] = t.y;
+        r.m[2][3] = t.z;
+        r
+    }
+
+    pub fn scale(s: Vec3) -> Self {
+        let mut r = Self::identity();
+        r.m[0][0] = s.x;
+        r.m[1][1] = s.y;
+        r.m[2][2] = s.z;
+        r
+    }
+
+    // By `degrees` around `axis`, counterclockwise seen from its tip.
+    pub fn rotate(axis: Vec3, degrees: f32) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+        let mut r = Self::identity();
+        r.m[0][0] = cos + a.x * a.x * k;
+        r.m[0][1] = a.x * a.y * k - a.z * sin;
+        r.m[0][2] = a.x * a.z * k + a.y * sin;
+        r.m[1][0] = a.y * a.x * k + a.z * sin;
+        r.m[1][1] = cos + a.y * a.y * k;
+        r.m[1][2] = a.y * a.z * k - a.x * sin;
+        r.m[2][0] = a.z * a.x * k - a.y * sin;
+        r.m[2][1] = a.z * a.y * k + a.x * sin;
+        r.m[2][2] = cos + a.z * a.z * k;
+        r
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut r = Self::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                r.m[i][j] = self.m[j][i];
+            }
+        }
+        r
+    }
+
+    // Gauss-Jordan elimination with partial pivoting; None if singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut r = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            r.swap(col, pivot);
+
+            let inv = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= inv;
+                r[col][j] *= inv;
+            }
+            for i in (0..4).filter(|&i| i != col) {
+                let f = a[i][col];
+                for j in 0..4 {
+                    a[i][j] -= f * a[col][j];
+                    r[i][j] -= f * r[col][j];
+                }
+            }
+        }
+        Some(Self { m: r })
+    }
+
+    pub fn transform_point(&self, p: Point) -> Point {
+        self.transform_vector(p)
+            + Vec3 {
+                x: self.m[0][3],
+                y: self.m[1][3],
+                z: self.m[2][3],
+            }
+    }
+
+    // Ignores the translation.
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let row = |i: usize| self.m[i][0] * v.x + self.m[i][1] * v.y + self.m[i][2] * v.z;
+        Vec3 {
+            x: row(0),
+            y: row(1),
+            z: row(2),
+        }
+    }
+
+    // The linear part's columns, the images of the axes.
+    pub fn axes(&self) -> [Vec3; 3] {
+        [0, 1, 2].map(|j| Vec3 {
+            x: self.m[0][j],
+            y: self.m[1][j],
+            z: self.m[2][j],
+        })
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut r = [[0.0; 4]; 4];
+        for (i, row) in r.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Self { m: r }
+    }
+}
@@ -0,0 +1,531 @@
+use crate::hittable::Dielectric;
+use crate::hittable::DiffuseLight;
+use crate::hittable::Lambertian;
+use crate::hittable::Material;
+use crate::hittable::Metal;
+use crate::hittable::Object;
+use crate::triangle::MeshFace;
+use crate::triangle::TriangleMesh;
+use crate::triangle::Uv;
+use crate::vec::Color;
+use crate::vec::Point;
+use crate::vec::Vec3;
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ObjError::Io(_, e) => Some(e),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+fn read_file(path: &Path, files: &mut Vec<PathBuf>) -> Result<String, ObjError> {
+    files.push(path.to_path_buf());
+    fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))
+}
+
+struct LineParser<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl LineParser<'_> {
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message,
+        }
+    }
+
+    fn floats<const N: usize>(&self, args: &[&str]) -> Result<[f32; N], ObjError> {
+        if args.len() < N {
+            return Err(self.error(format!("expected {} numbers, got {}", N, args.len())));
+        }
+        let mut values = [0.0; N];
+        for (value, arg) in values.iter_mut().zip(args) {
+            *value = arg
+                .parse()
+                .ok()
+                .filter(|x: &f32| x.is_finite())
+                .ok_or_else(|| self.error(format!("invalid number '{}'", arg)))?;
+        }
+        Ok(values)
+    }
+
+    fn vec3(&self, args: &[&str]) -> Result<Vec3, ObjError> {
+        let [x, y, z] = self.floats(args)?;
+        Ok(Vec3 { x, y, z })
+    }
+
+    // OBJ indices are 1-based, negative ones count back from the latest element.
+    fn index(&self, arg: &str, len: usize) -> Result<usize, ObjError> {
+        let i: i64 = arg
+            .parse()
+            .map_err(|_| self.error(format!("invalid index '{}'", arg)))?;
+        let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+        if resolved < 0 || resolved >= len as i64 {
+            return Err(self.error(format!("index {} out of range", i)));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+#[derive(Default)]
+struct MtlMaterial {
+    diffuse: Option<Color>,
+    specular: Option<Color>,
+    emission: Option<Color>,
+    shininess: Option<f32>,
+    ior: Option<f32>,
+    dissolve: Option<f32>,
+    illum: Option<u32>,
+}
+
+impl MtlMaterial {
+    fn to_material(&self) -> Box<dyn Material> {
+        let is_black = |c: Color| c.x.max(c.y).max(c.z) <= 0.0;
+        if let Some(emission) = self.emission.filter(|&e| !is_black(e)) {
+            return Box::new(DiffuseLight {
+                color: emission,
+                intensity: 1.0,
+                two_sided: false,
+            });
+        }
+
+        let transmissive = self.dissolve.is_some_and(|d| d < 1.0)
+            || matches!(self.illum, Some(4) | Some(6) | Some(7) | Some(9));
+        if transmissive {
+            return Box::new(Dielectric {
+                ir: self.ior.unwrap_or(1.5),
+            });
+        }
+
+        let specular = self.specular.unwrap_or_default();
+        if matches!(self.illum, Some(3) | Some(5)) && !is_black(specular) {
+            // Map the Phong exponent to a roughness-like fuzziness.
+            let ns = self.shininess.unwrap_or(0.0).max(0.0);
+            return Box::new(Metal {
+                albedo: specular,
+                fuzziness: (2.0 / (ns + 2.0)).sqrt(),
+            });
+        }
+
+        Box::new(Lambertian {
+            albedo: self.diffuse.unwrap_or(DEFAULT_ALBEDO),
+        })
+    }
+}
+
+const DEFAULT_ALBEDO: Color = Color {
+    x: 0.8,
+    y: 0.8,
+    z: 0.8,
+};
+
+fn load_mtl(
+    path: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let content = read_file(path, files)?;
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (i, line) in content.lines().enumerate() {
+        let p = LineParser { path, line: i + 1 };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, args) = match tokens.split_first() {
+            Some((k, _)) if k.starts_with('#') => continue,
+            Some((k, args)) => (*k, args),
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            if let Some((name, m)) = current.take() {
+                materials.insert(name, m);
+            }
+            current = Some((args.join(" "), Default::default()));
+            continue;
+        }
+
+        let m = match current.as_mut() {
+            Some((_, m)) => m,
+            None => return Err(p.error(format!("'{}' before newmtl", keyword))),
+        };
+        match keyword {
+            "Kd" => m.diffuse = Some(p.vec3(args)?),
+            "Ks" => m.specular = Some(p.vec3(args)?),
+            "Ke" => m.emission = Some(p.vec3(args)?),
+            "Ns" => m.shininess = Some(p.floats::<1>(args)?[0]),
+            "Ni" => m.ior = Some(p.floats::<1>(args)?[0]),
+            "d" => m.dissolve = Some(p.floats::<1>(args)?[0]),
+            "Tr" => m.dissolve = Some(1.0 - p.floats::<1>(args)?[0]),
+            "illum" => m.illum = Some(p.floats::<1>(args)?[0] as u32),
+            _ => {}
+        }
+    }
+
+    if let Some((name, m)) = current {
+        materials.insert(name, m);
+    }
+    Ok(materials)
+}
+
+#[derive(Default)]
+struct FaceGroup {
+    vertices: Vec<Point>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Uv>,
+    faces: Vec<MeshFace>,
+    vertex_map: HashMap<usize, usize>,
+    normal_map: HashMap<usize, usize>,
+    uv_map: HashMap<usize, usize>,
+}
+
+// Maps an index into the file-wide buffer to one into the group's own buffer.
+fn remap<T: Copy>(i: usize, map: &mut HashMap<usize, usize>, src: &[T], dst: &mut Vec<T>) -> usize {
+    *map.entry(i).or_insert_with(|| {
+        dst.push(src[i]);
+        dst.len() - 1
+    })
+}
+
+struct ObjCorner {
+    vertex: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Loads a Wavefront OBJ file into one object per material used, with
+// materials taken from the referenced MTL libraries. An override material
+// replaces them all and yields a single object. The paths of the files read
+// are added to `files`.
+pub fn load_obj(
+    path: &Path,
+    material_override: Option<Box<dyn Material>>,
+    files: &mut Vec<PathBuf>,
+) -> Result<Vec<Object>, ObjError> {
+    let content = read_file(path, files)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut mtl_materials = HashMap::new();
+    let mut groups: Vec<(Option<String>, FaceGroup)> = vec![(None, Default::default())];
+    let mut current_group = 0;
+
+    for (i, line) in content.lines().enumerate() {
+        let p = LineParser { path, line: i + 1 };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, args) = match tokens.split_first() {
+            Some((k, _)) if k.starts_with('#') => continue,
+            Some((k, args)) => (*k, args),
+            None => continue,
+        };
+
+        match keyword {
+            "v" => vertices.push(p.vec3(args)?),
+            "vn" => normals.push(p.vec3(args)?),
+            "vt" => {
+                let [u] = p.floats(args)?;
+                let v = match args.get(1) {
+                    Some(_) => p.floats::<2>(args)?[1],
+                    None => 0.0,
+                };
+                uvs.push(Uv { u, v });
+            }
+            "mtllib" => {
+                for lib in args {
+                    mtl_materials.extend(load_mtl(&base_dir.join(lib), files)?);
+                }
+            }
+            "usemtl" if material_override.is_none() => {
+                let name = Some(args.join(" "));
+                current_group = match groups.iter().position(|(n, _)| *n == name) {
+                    Some(g) => g,
+                    None => {
+                        groups.push((name, Default::default()));
+                        groups.len() - 1
+                    }
+                };
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(p.error("face with fewer than 3 vertices".to_string()));
+                }
+                let mut corners = Vec::with_capacity(args.len());
+                for arg in args {
+                    let mut parts = arg.split('/');
+                    let vertex = p.index(parts.next().unwrap_or(""), vertices.len())?;
+                    let uv = match parts.next() {
+                        Some(s) if !s.is_empty() => Some(p.index(s, uvs.len())?),
+                        _ => None,
+                    };
+                    let normal = match parts.next() {
+                        Some(s) if !s.is_empty() => Some(p.index(s, normals.len())?),
+                        _ => None,
+                    };
+                    corners.push(ObjCorner { vertex, uv, normal });
+                }
+
+                let group = &mut groups[current_group].1;
+                // Fan triangulation, assuming convex polygons.
+                for k in 1..corners.len() - 1 {
+                    let tri = [&corners[0], &corners[k], &corners[k + 1]];
+                    let face = MeshFace {
+                        vertices: tri.map(|c| {
+                            remap(
+                                c.vertex,
+                                &mut group.vertex_map,
+                                &vertices,
+                                &mut group.vertices,
+                            )
+                        }),
+                        normals: tri.iter().all(|c| c.normal.is_some()).then(|| {
+                            tri.map(|c| {
+                                remap(
+                                    c.normal.unwrap(),
+                                    &mut group.normal_map,
+                                    &normals,
+                                    &mut group.normals,
+                                )
+                            })
+                        }),
+                        uvs: tri.iter().all(|c| c.uv.is_some()).then(|| {
+                            tri.map(|c| {
+                                remap(c.uv.unwrap(), &mut group.uv_map, &uvs, &mut group.uvs)
+                            })
+                        }),
+                    };
+                    group.faces.push(face);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut material_override = material_override;
+    Ok(groups
+        .into_iter()
+        .filter(|(_, group)| !group.faces.is_empty())
+        .map(|(name, group)| {
+            let mtl_material = name.and_then(|n| mtl_materials.get(&n));
+            let material = match (material_override.take(), mtl_material) {
+                (Some(material), _) => material,
+                (None, Some(m)) => m.to_material(),
+                (None, None) => Box::new(Lambertian {
+                    albedo: DEFAULT_ALBEDO,
+                }),
+            };
+            Object {
+                material,
+                hittable: Box::new(TriangleMesh::new(
+                    group.vertices,
+                    group.normals,
+                    group.uvs,
+                    group.faces,
+                )),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::load_obj;
+    use super::ObjError;
+    use crate::hittable::Object;
+    use crate::temp_dir::TempDir;
+
+    use serde_json::Value;
+    use std::fs;
+
+    // Writes the files into a fresh directory and loads the first.
+    fn load(test: &str, files: &[(&str, &str)]) -> Result<Vec<Object>, ObjError> {
+        let dir = TempDir::new(&format!("obj-{}", test));
+        for (name, content) in files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        load_obj(&dir.join(files[0].0), None, &mut Vec::new())
+    }
+
+    fn mesh(object: &Object) -> Value {
+        serde_json::to_value(object).unwrap()["hittable"].clone()
+    }
+
+    fn faces(object: &Object) -> Vec<Value> {
+        mesh(object)["faces"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn test_fan_triangulation() {
+        let objects = load(
+            "fan",
+            &[(
+                "pentagon.obj",
+                "v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5\n",
+            )],
+        )
+        .unwrap();
+        assert_eq!(objects.len(), 1);
+        let vertices: Vec<Value> = faces(&objects[0])
+            .iter()
+            .map(|f| f["vertices"].clone())
+            .collect();
+        assert_eq!(
+            vertices,
+            [
+                serde_json::json!([0, 1, 2]),
+                serde_json::json!([0, 2, 3]),
+                serde_json::json!([0, 3, 4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_index_forms() {
+        let objects = load(
+            "forms",
+            &[(
+                "forms.obj",
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                 vt 0 0\nvt 1 0\nvt 0 1\n\
+                 vn 0 0 1\nvn 0 0 1\nvn 0 0 1\n\
+                 f -3 -2 -1\n\
+                 f 1/1 2/2 3/3\n\
+                 f 1//1 2//2 3//3\n\
+                 f 1/-3/-3 2/-2/-2 3/-1/-1\n",
+            )],
+        )
+        .unwrap();
+        let faces = faces(&objects[0]);
+        let has = |f: &Value, key: &str| !f[key].is_null();
+        let forms: Vec<(bool, bool)> = faces
+            .iter()
+            .map(|f| (has(f, "uvs"), has(f, "normals")))
+            .collect();
+        assert_eq!(
+            forms,
+            [(false, false), (true, false), (false, true), (true, true)]
+        );
+        for f in &faces {
+            assert_eq!(f["vertices"], serde_json::json!([0, 1, 2]));
+        }
+        // Negative indices refer to the same elements as the positive ones.
+        assert_eq!(faces[3]["uvs"], faces[1]["uvs"]);
+        assert_eq!(faces[3]["normals"], faces[2]["normals"]);
+    }
+
+    #[test]
+    fn test_usemtl_groups_and_mtl_materials() {
+        let objects = load(
+            "mtl",
+            &[
+                (
+                    "scene.obj",
+                    "mtllib scene.mtl\n\
+                     v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                     usemtl matte\nf 1 2 3\n\
+                     usemtl mirror\nf 1 2 3\n\
+                     usemtl matte\nf 3 2 1\n\
+                     usemtl glass\nf 1 2 3\n\
+                     usemtl lamp\nf 1 2 3\n",
+                ),
+                (
+                    "scene.mtl",
+                    "newmtl matte\nKd 0.5 0.25 0.125\n\
+                     newmtl mirror\nillum 3\nKs 0.9 0.9 0.9\nNs 1000\n\
+                     newmtl glass\nillum 7\nNi 1.33\n\
+                     newmtl lamp\nKe 4 4 4\n",
+                ),
+            ],
+        )
+        .unwrap();
+        let summary: Vec<(String, usize)> = objects
+            .iter()
+            .map(|o| {
+                let material = serde_json::to_value(o).unwrap()["material"]["type"].clone();
+                (material.as_str().unwrap().to_string(), faces(o).len())
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Lambertian".to_string(), 2),
+                ("Metal".to_string(), 1),
+                ("Dielectric".to_string(), 1),
+                ("DiffuseLight".to_string(), 1),
+            ]
+        );
+        let materials: Vec<Value> = objects
+            .iter()
+            .map(|o| serde_json::to_value(o).unwrap()["material"].clone())
+            .collect();
+        assert_eq!(materials[0]["albedo"]["y"], 0.25);
+        assert_eq!(materials[2]["ir"].as_f64().unwrap() as f32, 1.33);
+        assert_eq!(materials[3]["color"]["x"], 4.0);
+    }
+
+    #[test]
+    fn test_index_out_of_range() {
+        let objects = load(
+            "range",
+            &[("bad.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 4\n")],
+        );
+        match objects {
+            Err(ObjError::Parse { line, message, .. }) => {
+                assert_eq!((line, message.as_str()), (5, "index 4 out of range"));
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn test_non_finite_numbers() {
+        for value in ["nan", "inf", "-inf", "1e39"] {
+            let objects = load(
+                "finite",
+                &[("bad.obj", &format!("v 0 0 0\nv {} 0 0\n", value))],
+            );
+            match objects {
+                Err(ObjError::Parse { line, message, .. }) => {
+                    assert_eq!(line, 2);
+                    assert_eq!(message, format!("invalid number '{}'", value));
+                }
+                _ => panic!("expected a parse error for {}", value),
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::aabb::Aabb;
+use crate::error::ValidationError;
+use crate::hittable::area_to_solid_angle_pdf;
+use crate::hittable::validate_point;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::hittable::LightSample;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::dot;
+use crate::vec::orthonormal_basis;
+use crate::vec::Point;
+use crate::vec::Vec3;
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+// Where the ray meets the plane through `p` with normal `n`, if it isn't
+// parallel to it.
+pub(crate) fn intersect_plane_ray(p: Point, n: Vec3, r: Ray) -> Option<f32> {
+    let denom = dot(n, r.d);
+    if denom.abs() < 1e-12 {
+        return None;
+    }
+    Some(dot(n, p - r.origin) / denom)
+}
+
+// The front face is the side `outward_n` points to.
+pub(crate) fn planar_hit_record(r: Ray, t: f32, outward_n: Vec3) -> HitRecord {
+    let front_face = dot(outward_n, r.d) <= 0.0;
+    HitRecord {
+        t,
+        p: r.at(t),
+        n: if front_face { outward_n } else { -outward_n },
+        front_face,
+    }
+}
+
+pub(crate) fn validate_normal(n: Vec3, path: String, errors: &mut Vec<ValidationError>) {
+    if !n.is_finite() || n.length_squared() == 0.0 {
+        errors.push(ValidationError::new(path, "must be finite and non-zero"));
+    }
+}
+
+// An infinite plane through `p`, facing `n`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Plane {
+    pub p: Point,
+    pub n: Vec3,
+}
+
+#[typetag::serde]
+impl Hittable for Plane {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        intersect_plane_ray(self.p, self.n, r)
+            .map(|t| planar_hit_record(r, t, self.n.unit()))
+            .into_iter()
+            .collect()
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        intersect_plane_ray(self.p, self.n, r)
+            .filter(|&t| t_min <= t && t <= t_max)
+            .map(|t| planar_hit_record(r, t, self.n.unit()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_point(self.p, format!("{}.p", path), errors);
+        validate_normal(self.n, format!("{}.n", path), errors);
+    }
+}
+
+// A disk of radius `r` around `o`, facing `n`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Disk {
+    pub o: Point,
+    pub n: Vec3,
+    pub r: f32,
+}
+
+impl Disk {
+    fn area(&self) -> f32 {
+        PI * self.r * self.r
+    }
+
+    fn intersect(&self, r: Ray) -> Option<f32> {
+        intersect_plane_ray(self.o, self.n, r)
+            .filter(|&t| (r.at(t) - self.o).length_squared() <= self.r * self.r)
+    }
+}
+
+#[typetag::serde]
+impl Hittable for Disk {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        self.intersect(r)
+            .map(|t| planar_hit_record(r, t, self.n.unit()))
+            .into_iter()
+            .collect()
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.intersect(r)
+            .filter(|&t| t_min <= t && t <= t_max)
+            .map(|t| planar_hit_record(r, t, self.n.unit()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // The disk reaches r * sin of the angle between the normal and each
+        // axis.
+        let n = self.n.unit();
+        let extent = |c: f32| self.r * (1.0 - c * c).max(0.0).sqrt();
+        let e = Vec3 {
+            x: extent(n.x),
+            y: extent(n.y),
+            z: extent(n.z),
+        };
+        Some(Aabb::new(self.o - e, self.o + e).padded())
+    }
+
+    fn sample(&self, origin: Point, sampler: &mut Sampler) -> Option<LightSample> {
+        let n = self.n.unit();
+        let (u, v) = orthonormal_basis(n);
+        let d = sampler.in_disk();
+        let p = self.o + (u * d.x + v * d.y) * self.r;
+        LightSample::from_area(origin, p, n, self.area())
+    }
+
+    fn sample_pdf(&self, origin: Point, h: HitRecord) -> f32 {
+        area_to_solid_angle_pdf(origin, h.p, self.n.unit(), self.area())
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_point(self.o, format!("{}.o", path), errors);
+        validate_normal(self.n, format!("{}.n", path), errors);
+        if !(self.r > 0.0 && self.r.is_finite()) {
+            errors.push(ValidationError::new(
+                format!("{}.r", path),
+                "radius must be positive",
+            ));
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use crate::aabb::Aabb;
+use crate::error::ValidationError;
+use crate::hittable::area_to_solid_angle_pdf;
+use crate::hittable::validate_point;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::hittable::LightSample;
+use crate::plane::intersect_plane_ray;
+use crate::plane::planar_hit_record;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec;
+use crate::vec::cross;
+use crate::vec::dot;
+use crate::vec::Point;
+use crate::vec::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+// The parallelogram spanned by `u` and `v` from the corner `q`, facing
+// cross(u, v). Axis-aligned rectangles have `u` and `v` along the axes.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Quad {
+    pub q: Point,
+    pub u: Vec3,
+    pub v: Vec3,
+}
+
+impl Quad {
+    fn normal(&self) -> Vec3 {
+        cross(self.u, self.v)
+    }
+
+    fn area(&self) -> f32 {
+        self.normal().length()
+    }
+
+    fn intersect(&self, r: Ray) -> Option<f32> {
+        let n = self.normal();
+        let t = intersect_plane_ray(self.q, n, r)?;
+        // Coordinates of the hit along u and v.
+        let w = n / dot(n, n);
+        let p = r.at(t) - self.q;
+        let alpha = dot(w, cross(p, self.v));
+        let beta = dot(w, cross(self.u, p));
+        ((0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta)).then_some(t)
+    }
+
+    fn random_point(&self, sampler: &mut Sampler) -> Point {
+        let (s, t) = sampler.get_2d();
+        self.q + self.u * s + self.v * t
+    }
+}
+
+#[typetag::serde]
+impl Hittable for Quad {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        self.intersect(r)
+            .map(|t| planar_hit_record(r, t, self.normal().unit()))
+            .into_iter()
+            .collect()
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.intersect(r)
+            .filter(|&t| t_min <= t && t <= t_max)
+            .map(|t| planar_hit_record(r, t, self.normal().unit()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let min = corners.into_iter().fold(self.q, vec::min);
+        let max = corners.into_iter().fold(self.q, vec::max);
+        Some(Aabb::new(min, max).padded())
+    }
+
+    fn sample(&self, origin: Point, sampler: &mut Sampler) -> Option<LightSample> {
+        let p = self.random_point(sampler);
+        LightSample::from_area(origin, p, self.normal().unit(), self.area())
+    }
+
+    fn sample_pdf(&self, origin: Point, h: HitRecord) -> f32 {
+        area_to_solid_angle_pdf(origin, h.p, self.normal().unit(), self.area())
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_point(self.q, format!("{}.q", path), errors);
+        validate_point(self.u, format!("{}.u", path), errors);
+        validate_point(self.v, format!("{}.v", path), errors);
+        if self.area() == 0.0 {
+            errors.push(ValidationError::new(path, "quad is degenerate"));
+        }
+    }
+}
+
+const AXES: [Vec3; 3] = [
+    Vec3 {
+        x: 1.0,
+        y: 0.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    },
+];
+
+// An axis-aligned box between the corners `min` and `max`, made of six quads
+// facing outwards.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Cuboid {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Cuboid {
+    fn sides(&self) -> [Quad; 6] {
+        let (a, b) = (self.min, self.max);
+        let dx = Vec3 {
+            x: b.x - a.x,
+            y: 0.0,
+            z: 0.0,
+        };
+        let dy = Vec3 {
+            x: 0.0,
+            y: b.y - a.y,
+            z: 0.0,
+        };
+        let dz = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: b.z - a.z,
+        };
+        let quad = |q: Point, u: Vec3, v: Vec3| Quad { q, u, v };
+        [
+            quad(a, dz, dy),
+            quad(a + dx, dy, dz),
+            quad(a, dx, dz),
+            quad(a + dy, dz, dx),
+            quad(a, dy, dx),
+            quad(a + dz, dx, dy),
+        ]
+    }
+
+    fn area(&self) -> f32 {
+        self.sides().iter().map(|s| s.area()).sum()
+    }
+}
+
+#[typetag::serde(name = "Box")]
+impl Hittable for Cuboid {
+    // A slab test rather than the six sides, so a ray through an edge or a
+    // corner enters and leaves once instead of hitting every side that
+    // meets there.
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        let (mut t_in, mut t_out) = (f32::NEG_INFINITY, f32::INFINITY);
+        let (mut n_in, mut n_out) = (Vec3::default(), Vec3::default());
+        for (axis, unit) in AXES.into_iter().enumerate() {
+            let inv_d = 1.0 / r.d[axis];
+            let mut t0 = (self.min[axis] - r.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - r.origin[axis]) * inv_d;
+            let mut n = unit;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+                n = -n;
+            }
+            // A ray in the plane of a side gives NaN, which changes nothing.
+            if t0 > t_in {
+                (t_in, n_in) = (t0, -n);
+            }
+            if t1 < t_out {
+                (t_out, n_out) = (t1, n);
+            }
+        }
+        if !(t_in.is_finite() && t_out.is_finite() && t_in <= t_out) {
+            return Vec::new();
+        }
+        vec![
+            planar_hit_record(r, t_in, n_in),
+            planar_hit_record(r, t_out, n_out),
+        ]
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.sides()
+            .iter()
+            .filter_map(|s| s.closest_hit(r, t_min, t_max))
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+
+    // Uniform by area over the whole surface.
+    fn sample(&self, origin: Point, sampler: &mut Sampler) -> Option<LightSample> {
+        let sides = self.sides();
+        let area = self.area();
+        let mut x = sampler.get_1d() * area;
+        let side = sides
+            .iter()
+            .find(|s| {
+                x -= s.area();
+                x < 0.0
+            })
+            .unwrap_or(&sides[5]);
+        let p = side.random_point(sampler);
+        LightSample::from_area(origin, p, side.normal().unit(), area)
+    }
+
+    fn sample_pdf(&self, origin: Point, h: HitRecord) -> f32 {
+        area_to_solid_angle_pdf(origin, h.p, h.n, self.area())
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_point(self.min, format!("{}.min", path), errors);
+        validate_point(self.max, format!("{}.max", path), errors);
+        let extent = self.max - self.min;
+        if !(extent.x > 0.0 && extent.y > 0.0 && extent.z > 0.0) {
+            errors.push(ValidationError::new(
+                format!("{}.max", path),
+                "must be greater than min on every axis",
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cuboid;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::vec::Point;
+    use crate::vec::Vec3;
+
+    #[test]
+    fn test_box_hits_have_outward_normals() {
+        let cuboid = Cuboid {
+            min: Point {
+                x: -1.0,
+                y: -1.0,
+                z: -1.0,
+            },
+            max: Point {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+            },
+        };
+        let axes = [
+            Vec3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            },
+        ];
+        for axis in axes {
+            for d in [axis, -axis] {
+                // From outside, through the middle of the box.
+                let origin = Point {
+                    x: 0.1,
+                    y: 0.2,
+                    z: 0.3,
+                } - d * 10.0;
+                let mut hits = cuboid.hit(Ray { origin, d });
+                hits.sort_by(|a, b| a.t.total_cmp(&b.t));
+                assert_eq!(hits.len(), 2);
+                assert!(hits[0].front_face && !hits[1].front_face);
+                assert_eq!(hits[0].n, -d);
+                assert_eq!(hits[1].n, -d);
+            }
+        }
+    }
+}
@@ -1,33 +1,76 @@
-use crate::vec::dot;
-use crate::vec::Vec3;
-
-use rand::random;
-
-pub fn get_random_offset() -> f32 {
-    random::<f32>() * 2.0 - 1.0
+// PCG32 (O'Neill, "PCG: A Family of Simple Fast Space-Efficient Statistically
+// Good Algorithms for Random Number Generation"). Implemented here rather than
+// taken from a crate so a seed gives the same image on every platform and
+// dependency version.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
 }
 
-pub fn get_random_float() -> f32 {
-    random::<f32>()
+const MULTIPLIER: u64 = 6364136223846793005;
+
+// SplitMix64 finaliser, used to spread structured inputs over all 64 bits.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
 
-pub fn random_in_sphere() -> Vec3 {
-    loop {
-        let v = Vec3 {
-            x: get_random_offset(),
-            y: get_random_offset(),
-            z: get_random_offset(),
+impl Rng {
+    // Generators with different streams give independent sequences even when
+    // seeded alike.
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            inc: (stream << 1) | 1,
         };
-        if v.length() <= 1.0 {
-            return v;
-        }
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random(), rand::random())
+    }
+
+    // The generator for one sample of one pixel, so the result does not
+    // depend on which thread renders it or in what order.
+    pub fn for_sample(seed: u64, pixel: u64, sample: u64) -> Self {
+        Self::new(seed, mix(pixel).wrapping_add(sample))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    // Uniform in [0, 1).
+    pub fn float(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    // Uniform in [-1, 1).
+    pub fn offset(&mut self) -> f32 {
+        self.float() * 2.0 - 1.0
     }
 }
 
-pub fn random_in_hemisphere(n: Vec3) -> Vec3 {
-    let v = random_in_sphere();
-    match dot(v, n) < 0.0 {
-        true => -v,
-        _ => v,
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn test_pcg32_reference_output() {
+        // First outputs of the PCG reference implementation's pcg32-demo.
+        let mut rng = Rng::new(42, 54);
+        let expected = [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b];
+        for e in expected {
+            assert_eq!(rng.next_u32(), e);
+        }
     }
 }
@@ -0,0 +1,733 @@
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::error::Result;
+use crate::error::ValidationError;
+use crate::film::Film;
+use crate::film::FilmTile;
+use crate::film::Filter;
+use crate::hittable::World;
+use crate::hittable::WorldHitRecord;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::sampler::SamplerKind;
+use crate::scene;
+use crate::scene::Scene;
+use crate::tile;
+use crate::tile::Tile;
+use crate::tile::TileOrder;
+use crate::tonemap::PostProcessConfig;
+use crate::vec::Color;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+// Direct light at a hit through a sampled point on an emitter, MIS-weighted
+// against the chance of the BSDF sampling the same direction.
+fn sample_direct_light(r: Ray, w: &World, h: &WorldHitRecord, sampler: &mut Sampler) -> Color {
+    let origin = h.hit_record.p + 1e-3 * h.hit_record.n;
+    let (light_id, light_sample) = match w.sample_light(origin, sampler) {
+        Some(s) => s,
+        None => return Default::default(),
+    };
+
+    let shadow_ray = Ray {
+        origin,
+        d: light_sample.hit_record.p - origin,
+    };
+    let f = h.material.eval(r, h.hit_record, shadow_ray.d);
+    if f == Color::default() || w.closest_hit(shadow_ray, 0.0001, 1.0 - 1e-3).is_some() {
+        return Default::default();
+    }
+
+    let emitted = w
+        .object(light_id)
+        .material
+        .emitted(shadow_ray, light_sample.hit_record);
+    let bsdf_pdf = h.material.pdf(r, h.hit_record, shadow_ray.d);
+    f * emitted * (power_heuristic(light_sample.pdf, bsdf_pdf) / light_sample.pdf)
+}
+
+fn ray_color(
+    r: Ray,
+    w: &World,
+    background: &Background,
+    depth: u32,
+    sampler: &mut Sampler,
+) -> Color {
+    let mut r = r;
+    let mut color: Color = Default::default();
+    let mut throughput = Color {
+        x: 1.0,
+        y: 1.0,
+        z: 1.0,
+    };
+    // Density of the BSDF sample that produced `r`, None for camera rays and
+    // specular bounces where light sampling had no say.
+    let mut bsdf_pdf: Option<f32> = None;
+
+    for _ in 0..depth {
+        let h = match w.closest_hit(r, 0.0001, f32::MAX) {
+            None => return color + throughput * background.color(r),
+            Some(h) => h,
+        };
+
+        let emitted = h.material.emitted(r, h.hit_record);
+        if emitted != Color::default() {
+            let weight = match bsdf_pdf {
+                None => 1.0,
+                Some(pdf) => power_heuristic(pdf, w.light_pdf(h.object_id, r.origin, h.hit_record)),
+            };
+            color = color + throughput * emitted * weight;
+        }
+
+        let s = match h.material.scatter(r, h.hit_record, sampler) {
+            Some(s) => s,
+            None => break,
+        };
+        if s.pdf.is_some() {
+            color = color + throughput * sample_direct_light(r, w, &h, sampler);
+        }
+
+        throughput = throughput * s.attenuation;
+        bsdf_pdf = s.pdf;
+        r = s.scattered_ray;
+    }
+
+    color
+}
+
+fn luminance(c: Color) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+// Running mean and variance of a pixel's sample luminance, by Welford's method.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PixelStats {
+    pub(crate) n: u32,
+    pub(crate) mean: f32,
+    pub(crate) m2: f32,
+}
+
+impl PixelStats {
+    fn add(&mut self, x: f32) {
+        self.n += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.n as f32;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    // Whether the standard error of the mean is within `threshold` of the
+    // mean, which is floored so near-black pixels can converge too.
+    fn converged(&self, threshold: f32) -> bool {
+        if self.n < 2 {
+            return false;
+        }
+        let variance = self.m2 / (self.n - 1) as f32;
+        (variance / self.n as f32).sqrt() <= threshold * self.mean.max(0.01)
+    }
+}
+
+fn default_min_samples() -> u32 {
+    16
+}
+
+fn default_max_samples() -> u32 {
+    1024
+}
+
+fn default_noise_threshold() -> f32 {
+    0.01
+}
+
+// Sampling that stops on a pixel once the standard error of its mean
+// luminance falls below `noise_threshold` times that luminance.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct AdaptiveSampling {
+    #[serde(default = "default_min_samples")]
+    pub min_samples: u32,
+    #[serde(default = "default_max_samples")]
+    pub max_samples: u32,
+    #[serde(default = "default_noise_threshold")]
+    pub noise_threshold: f32,
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        Self {
+            min_samples: default_min_samples(),
+            max_samples: default_max_samples(),
+            noise_threshold: default_noise_threshold(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct RenderConfig {
+    pub aspect_ratio: f32,
+    pub img_width: usize,
+    pub img_height: usize,
+    pub number_of_samples: u32,
+    // Replaces the fixed number_of_samples when set.
+    pub adaptive: Option<AdaptiveSampling>,
+    pub depth: u32,
+    // None draws a fresh seed from the OS for every render. A given seed gives
+    // the same image however the work is split between threads.
+    pub seed: Option<u64>,
+    // Seconds to stop after, with whatever samples have been taken by then.
+    pub time_limit: Option<f32>,
+    pub sampler: SamplerKind,
+    pub filter: Filter,
+    // Width and height in pixels of the squares a pass is split into.
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub post_process: PostProcessConfig,
+}
+
+impl RenderConfig {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        scene::deserialize_from_file(path)
+    }
+
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if self.img_width < 2 || self.img_height < 2 {
+            errors.push(ValidationError::new(
+                "img_width",
+                "image must be at least 2x2 pixels",
+            ));
+        }
+        if self.number_of_samples == 0 {
+            errors.push(ValidationError::new(
+                "number_of_samples",
+                "must be positive",
+            ));
+        }
+        if let Some(adaptive) = &self.adaptive {
+            if adaptive.min_samples == 0 {
+                errors.push(ValidationError::new(
+                    "adaptive.min_samples",
+                    "must be positive",
+                ));
+            }
+            if adaptive.max_samples < adaptive.min_samples {
+                errors.push(ValidationError::new(
+                    "adaptive.max_samples",
+                    "must be at least min_samples",
+                ));
+            }
+            if !(adaptive.noise_threshold > 0.0 && adaptive.noise_threshold.is_finite()) {
+                errors.push(ValidationError::new(
+                    "adaptive.noise_threshold",
+                    "must be positive",
+                ));
+            }
+        }
+        if self.depth == 0 {
+            errors.push(ValidationError::new("depth", "must be positive"));
+        }
+        if let Some(time_limit) = self.time_limit {
+            if !(time_limit > 0.0 && time_limit.is_finite()) {
+                errors.push(ValidationError::new("time_limit", "must be positive"));
+            }
+        }
+        self.filter.validate("filter", &mut errors);
+        self.post_process.validate("post_process", &mut errors);
+        if self.tile_size == 0 {
+            errors.push(ValidationError::new("tile_size", "must be positive"));
+        }
+        if !(self.aspect_ratio > 0.0 && self.aspect_ratio.is_finite()) {
+            errors.push(ValidationError::new("aspect_ratio", "must be positive"));
+        } else if !self.fits_aspect_ratio(self.aspect_ratio) {
+            errors.push(ValidationError::new(
+                "img_height",
+                format!(
+                    "{}x{} does not match aspect_ratio {}",
+                    self.img_width, self.img_height, self.aspect_ratio
+                ),
+            ));
+        }
+        errors
+    }
+
+    // Problems that only show up with a given scene, such as a camera that
+    // would be stretched to fit the image.
+    pub fn validate_for(&self, scene: &Scene) -> Vec<ValidationError> {
+        let aspect_ratio = scene.camera_config.aspect_ratio;
+        if aspect_ratio > 0.0 && !self.fits_aspect_ratio(aspect_ratio) {
+            return vec![ValidationError::new(
+                "camera_config.aspect_ratio",
+                format!(
+                    "{} does not match the {}x{} image",
+                    aspect_ratio, self.img_width, self.img_height
+                ),
+            )];
+        }
+        Vec::new()
+    }
+
+    // The fewest and most samples a pixel can get.
+    pub fn sample_range(&self) -> (u32, u32) {
+        match &self.adaptive {
+            Some(adaptive) => (adaptive.min_samples, adaptive.max_samples),
+            None => (self.number_of_samples, self.number_of_samples),
+        }
+    }
+
+    // Allows for the height having been rounded to whole pixels.
+    fn fits_aspect_ratio(&self, aspect_ratio: f32) -> bool {
+        (self.img_width as f32 / aspect_ratio - self.img_height as f32).abs() <= 1.0
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            aspect_ratio: 16.0 / 9.0,
+            img_width: 400,
+            img_height: 225,
+            number_of_samples: 100,
+            adaptive: None,
+            depth: 30,
+            seed: None,
+            time_limit: None,
+            sampler: Default::default(),
+            filter: Default::default(),
+            tile_size: 16,
+            tile_order: Default::default(),
+            post_process: Default::default(),
+        }
+    }
+}
+
+pub struct Renderer {
+    camera: Camera,
+    world: World,
+    background: Background,
+    config: RenderConfig,
+    tiles: Vec<Tile>,
+}
+
+impl Renderer {
+    pub fn new(scene: Scene, config: RenderConfig) -> Self {
+        Self {
+            camera: Camera::new(scene.camera_config),
+            world: scene.world,
+            background: scene.background,
+            tiles: tile::tiles(
+                config.img_width,
+                config.img_height,
+                config.tile_size,
+                config.tile_order,
+            ),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &RenderConfig {
+        &self.config
+    }
+
+    // The pieces of work each pass is split into, in the order they are
+    // started.
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    pub fn render(&self) -> Film {
+        self.render_progressive(None, &AtomicBool::new(false), |_| {})
+            .film
+    }
+
+    // A render with no samples taken yet.
+    pub fn start(&self) -> RenderState {
+        let config = &self.config;
+        RenderState {
+            film: Film::new(config.img_width, config.img_height, config.filter),
+            stats: vec![Default::default(); config.img_width * config.img_height],
+            seed: config.seed.unwrap_or_else(rand::random),
+            pass: Pass {
+                number: 0,
+                samples: 0..0,
+                elapsed: Duration::ZERO,
+                tile_times: Vec::new(),
+            },
+        }
+    }
+
+    // Renders in passes over the whole image, each doubling the samples per
+    // pixel up to MAX_PASS_SAMPLES, and calls `on_pass` after every one.
+    // Starts from `resume` if given. With a time limit, the pass that would
+    // overrun it is cut short to fit and the render stops there. Setting
+    // `cancel` abandons the current pass and returns what was done before it.
+    pub fn render_progressive<F: FnMut(&RenderState)>(
+        &self,
+        resume: Option<RenderState>,
+        cancel: &AtomicBool,
+        mut on_pass: F,
+    ) -> RenderState {
+        let config = &self.config;
+        let (_, max_samples) = config.sample_range();
+        let mut state = resume.unwrap_or_else(|| self.start());
+
+        // Time spent in earlier sessions counts towards the sampling rate but
+        // not the time limit.
+        let (start, earlier) = (Instant::now(), state.pass.elapsed);
+        while state.pass.samples.end < max_samples && !cancel.load(Ordering::Relaxed) {
+            let first = state.pass.samples.end;
+            let mut end = (first * 2).clamp(1, max_samples.min(first + MAX_PASS_SAMPLES));
+            if let Some(limit) = config.time_limit {
+                let remaining = limit - start.elapsed().as_secs_f32();
+                if first > 0 {
+                    let per_sample = state.pass.elapsed.as_secs_f32() / first as f32;
+                    end = end.min(first + (remaining / per_sample) as u32);
+                }
+                if end == first || remaining <= 0.0 {
+                    break;
+                }
+            }
+
+            let tile_times = match self.render_pass(&mut state, cancel, first..end) {
+                Some(times) => times,
+                None => break,
+            };
+            state.pass.number += 1;
+            state.pass.tile_times = tile_times;
+            state.pass.samples = first..end;
+            state.pass.elapsed = earlier + start.elapsed();
+            on_pass(&state);
+        }
+        state
+    }
+
+    // Adds samples with the given indices to every pixel that has not yet
+    // converged, a tile per task. Tiles are handed out in order as threads
+    // become free. Returns the time spent on each tile, or None, leaving
+    // `state` as it was, if cancelled part way.
+    fn render_pass(
+        &self,
+        state: &mut RenderState,
+        cancel: &AtomicBool,
+        samples: Range<u32>,
+    ) -> Option<Vec<Duration>> {
+        let config = &self.config;
+        let (min_samples, max_samples) = config.sample_range();
+        let threshold = config.adaptive.map_or(0.0, |a| a.noise_threshold);
+        let (width, height) = (config.img_width, config.img_height);
+        let (film, pixel_stats, seed) = (&state.film, &state.stats, state.seed);
+
+        let mut done: Vec<(usize, FilmTile, Vec<PixelStats>, Duration)> = self
+            .tiles
+            .iter()
+            .enumerate()
+            .par_bridge()
+            .map(|(k, t)| {
+                if cancel.load(Ordering::Relaxed) {
+                    return None;
+                }
+                let start = Instant::now();
+                let mut tile = film.tile(t.x.clone(), t.y.clone());
+                let mut tile_stats = Vec::with_capacity(t.x.len() * t.y.len());
+                for i in t.y.clone() {
+                    for j in t.x.clone() {
+                        let mut stats = pixel_stats[i * width + j];
+                        for s in samples.clone() {
+                            if s >= min_samples && stats.converged(threshold) {
+                                break;
+                            }
+                            let sampler =
+                                &mut Sampler::new(config.sampler, seed, j, i, s, max_samples);
+                            let (dx, dy) = sampler.get_2d();
+                            let (px, py) = (j as f32 + dx, i as f32 + dy);
+                            // Pixel centers map to the edges of the viewport.
+                            let u = (height as f32 - 0.5 - py) / (height - 1) as f32;
+                            let v = (px - 0.5) / (width - 1) as f32;
+                            let color = ray_color(
+                                self.camera.get_ray(u, v, sampler),
+                                &self.world,
+                                &self.background,
+                                config.depth,
+                                sampler,
+                            );
+                            stats.add(luminance(color));
+                            tile.add_sample(px, py, color);
+                        }
+                        tile_stats.push(stats);
+                    }
+                }
+                Some((k, tile, tile_stats, start.elapsed()))
+            })
+            .collect::<Option<_>>()?;
+
+        // Tiles finish in any order, so put them back in a fixed one for the
+        // sums not to depend on scheduling.
+        done.sort_by_key(|(k, ..)| *k);
+        let mut times = Vec::with_capacity(done.len());
+        for ((_, tile, tile_stats, time), t) in done.into_iter().zip(&self.tiles) {
+            state.film.merge_tile(&tile);
+            let pixels =
+                t.y.clone()
+                    .flat_map(|i| t.x.clone().map(move |j| i * width + j));
+            for (p, stats) in pixels.zip(tile_stats) {
+                state.stats[p] = stats;
+            }
+            times.push(time);
+        }
+        Some(times)
+    }
+}
+
+// Passes grow no larger than this, so snapshots and checkpoints keep coming
+// through long renders.
+const MAX_PASS_SAMPLES: u32 = 32;
+
+// Everything needed to carry on a progressive render. The random numbers for
+// a sample depend only on the seed and the sample's indices, so this also
+// fixes the state of every generator.
+pub struct RenderState {
+    pub film: Film,
+    pub(crate) stats: Vec<PixelStats>,
+    pub seed: u64,
+    // The last pass finished.
+    pub pass: Pass,
+}
+
+// A finished pass of a progressive render.
+pub struct Pass {
+    // Counting from 1.
+    pub number: u32,
+    // The sample indices the pass added to each pixel.
+    pub samples: Range<u32>,
+    // Since the render started.
+    pub elapsed: Duration,
+    // Time spent on each of Renderer::tiles() in this pass, summed over
+    // threads.
+    pub tile_times: Vec<Duration>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::luminance;
+    use super::ray_color;
+    use super::AdaptiveSampling;
+    use super::PixelStats;
+    use super::RenderConfig;
+    use super::Renderer;
+    use crate::background::Background;
+    use crate::error::ValidationError;
+    use crate::hittable::World;
+    use crate::ray::Ray;
+    use crate::sampler::Sampler;
+    use crate::sampler::SamplerKind;
+    use crate::scene::Scene;
+    use crate::tonemap::PostProcessConfig;
+    use crate::vec::Point;
+    use crate::vec::Vec3;
+
+    #[test]
+    fn test_pixel_stats() {
+        let mut stats = PixelStats::default();
+        for x in [1.0, 2.0, 3.0, 4.0] {
+            stats.add(x);
+        }
+        // Squared deviations from 2.5 sum to 5, for a sample variance of 5 / 3.
+        assert_eq!((stats.n, stats.mean, stats.m2), (4, 2.5, 5.0));
+        assert!(stats.converged(0.5));
+        assert!(!stats.converged(0.25));
+
+        let mut flat = PixelStats::default();
+        flat.add(0.0);
+        assert!(!flat.converged(1.0));
+        flat.add(0.0);
+        assert!(flat.converged(0.0));
+    }
+
+    #[test]
+    fn test_adaptive_sample_counts() {
+        // The top row looks up into the black sky. The bottom one looks down
+        // onto a floor lit by a small light behind the camera.
+        let scene: Scene = serde_json::from_str(
+            r#"{
+                "camera_config": {
+                    "lookfrom": {"x": 0, "y": 0, "z": 0},
+                    "lookat": {"x": 0, "y": 0, "z": -1},
+                    "vup": {"x": 0, "y": 1, "z": 0},
+                    "vfov": 90, "aspect_ratio": 2, "aperture": 0, "focus_dist": 1
+                },
+                "world": {"objects": [
+                    {
+                        "material": {"type": "Lambertian", "albedo": {"x": 0.5, "y": 0.5, "z": 0.5}},
+                        "hittable": {"type": "Plane", "p": {"x": 0, "y": -1, "z": 0}, "n": {"x": 0, "y": 1, "z": 0}}
+                    },
+                    {
+                        "material": {"type": "DiffuseLight", "color": {"x": 1, "y": 1, "z": 1}, "intensity": 10},
+                        "hittable": {"type": "Sphere", "o": {"x": 0, "y": 3, "z": 3}, "r": 0.5}
+                    }
+                ]},
+                "background": {"type": "Solid", "color": {"x": 0, "y": 0, "z": 0}}
+            }"#,
+        )
+        .unwrap();
+        let config = RenderConfig {
+            aspect_ratio: 2.0,
+            img_width: 4,
+            img_height: 2,
+            adaptive: Some(AdaptiveSampling {
+                min_samples: 8,
+                max_samples: 64,
+                noise_threshold: 1e-3,
+            }),
+            seed: Some(1),
+            ..Default::default()
+        };
+        assert!(config.validate_for(&scene).is_empty());
+
+        let counts = Renderer::new(scene, config).render().sample_counts();
+        assert_eq!(counts, vec![vec![8; 4], vec![64; 4]]);
+    }
+
+    #[test]
+    fn test_direct_light_known_answer() {
+        // A floor with albedo 0.5 under a sphere of radiance 2 and angular
+        // radius asin(1 / 4). Its BSDF times the cosine is 0.5 / 2π, so it
+        // reflects 0.5 * 2 / 2π times the solid angle 2π * (1 - √15 / 4).
+        let world: World = serde_json::from_str(
+            r#"{"objects": [
+                {
+                    "material": {"type": "Lambertian", "albedo": {"x": 0.5, "y": 0.5, "z": 0.5}},
+                    "hittable": {"type": "Plane", "p": {"x": 0, "y": 0, "z": 0}, "n": {"x": 0, "y": 1, "z": 0}}
+                },
+                {
+                    "material": {"type": "DiffuseLight", "color": {"x": 1, "y": 1, "z": 1}, "intensity": 2},
+                    "hittable": {"type": "Sphere", "o": {"x": 0, "y": 4, "z": 0}, "r": 1}
+                }
+            ]}"#,
+        )
+        .unwrap();
+        let background = Background::Solid {
+            color: Default::default(),
+        };
+        let r = Ray {
+            origin: Point {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            d: Vec3 {
+                x: 0.0,
+                y: -1.0,
+                z: 0.0,
+            },
+        };
+        let n = 4096;
+        let mean = (0..n)
+            .map(|i| {
+                let sampler = &mut Sampler::new(SamplerKind::Sobol, 1, 0, 0, i, n);
+                luminance(ray_color(r, &world, &background, 4, sampler))
+            })
+            .sum::<f32>()
+            / n as f32;
+        let expected = 1.0 - 15f32.sqrt() / 4.0;
+        assert!((mean - expected).abs() < expected * 0.01, "{}", mean);
+    }
+
+    #[test]
+    fn test_light_pdf_matches_sample_light() {
+        let world: World = serde_json::from_str(
+            r#"{"objects": [
+                {
+                    "material": {"type": "DiffuseLight", "color": {"x": 1, "y": 1, "z": 1}, "intensity": 1},
+                    "hittable": {"type": "Sphere", "o": {"x": -2, "y": 4, "z": 0}, "r": 1}
+                },
+                {
+                    "material": {"type": "DiffuseLight", "color": {"x": 1, "y": 1, "z": 1}, "intensity": 1},
+                    "hittable": {"type": "Quad", "q": {"x": 1, "y": 3, "z": -1}, "u": {"x": 2, "y": 0, "z": 0}, "v": {"x": 0, "y": 0, "z": 2}}
+                }
+            ]}"#,
+        )
+        .unwrap();
+        let origin = Point {
+            x: 0.5,
+            y: 0.0,
+            z: 0.25,
+        };
+        let mut seen = [false; 2];
+        for i in 0..64 {
+            let sampler = &mut Sampler::new(SamplerKind::Sobol, 1, 0, 0, i, 64);
+            let (id, s) = world.sample_light(origin, sampler).unwrap();
+            let pdf = world.light_pdf(id, origin, s.hit_record);
+            assert!((pdf - s.pdf).abs() <= 1e-3 * s.pdf, "{} {}", pdf, s.pdf);
+            seen[id as usize] = true;
+        }
+        assert_eq!(seen, [true, true]);
+    }
+
+    #[test]
+    fn test_render_config_validation() {
+        let config = RenderConfig {
+            img_width: 64,
+            img_height: 64,
+            depth: 0,
+            tile_size: 0,
+            post_process: PostProcessConfig {
+                exposure: f32::INFINITY,
+                gamma: Some(0.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            [
+                ValidationError::new("depth", "must be positive"),
+                ValidationError::new("post_process.exposure", "must be finite"),
+                ValidationError::new("post_process.gamma", "must be positive"),
+                ValidationError::new("tile_size", "must be positive"),
+                ValidationError::new(
+                    "img_height",
+                    format!("64x64 does not match aspect_ratio {}", 16.0f32 / 9.0)
+                ),
+            ]
+        );
+
+        // The config itself is consistent, but the camera is not 16:9.
+        let config = RenderConfig {
+            img_width: 64,
+            img_height: 36,
+            ..Default::default()
+        };
+        assert!(config.validate().is_empty());
+        let scene: Scene = serde_json::from_str(
+            r#"{
+                "camera_config": {
+                    "lookfrom": {"x": 0, "y": 0, "z": 0},
+                    "lookat": {"x": 0, "y": 0, "z": -1},
+                    "vup": {"x": 0, "y": 1, "z": 0},
+                    "vfov": 90, "aspect_ratio": 1, "aperture": 0, "focus_dist": 1
+                },
+                "world": {"objects": []}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.validate_for(&scene),
+            [ValidationError::new(
+                "camera_config.aspect_ratio",
+                "1 does not match the 64x36 image"
+            )]
+        );
+    }
+}
@@ -0,0 +1,533 @@
+// Shapes of revolution around an arbitrary axis. Rays are moved into a frame
+// where the axis is z, solved there and the normals moved back; the frame is
+// orthonormal, so distances along the ray are the same in both.
+use crate::aabb::Aabb;
+use crate::error::ValidationError;
+use crate::hittable::validate_point;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::plane::planar_hit_record;
+use crate::plane::validate_normal;
+use crate::ray::Ray;
+use crate::sphere::solve_quadratic;
+use crate::sphere::QuadraticSolution;
+use crate::vec;
+use crate::vec::dot;
+use crate::vec::orthonormal_basis;
+use crate::vec::Point;
+use crate::vec::Vec3;
+
+use serde::{Deserialize, Serialize};
+
+struct Frame {
+    o: Point,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Frame {
+    fn new(o: Point, axis: Vec3) -> Self {
+        let w = axis.unit();
+        let (u, v) = orthonormal_basis(w);
+        Self { o, u, v, w }
+    }
+
+    fn to_local(&self, d: Vec3) -> Vec3 {
+        Vec3 {
+            x: dot(d, self.u),
+            y: dot(d, self.v),
+            z: dot(d, self.w),
+        }
+    }
+
+    fn ray_to_local(&self, r: Ray) -> Ray {
+        Ray {
+            origin: self.to_local(r.origin - self.o),
+            d: self.to_local(r.d),
+        }
+    }
+
+    fn to_world(&self, d: Vec3) -> Vec3 {
+        self.u * d.x + self.v * d.y + self.w * d.z
+    }
+}
+
+fn quadratic_roots(a: f32, b: f32, c: f32) -> Vec<f32> {
+    if a.abs() < 1e-12 {
+        // Rays parallel to a cone's surface only cross it once.
+        return if b.abs() < 1e-12 {
+            Vec::new()
+        } else {
+            vec![-c / b]
+        };
+    }
+    match solve_quadratic(a, b, c) {
+        QuadraticSolution::NoSolution => Vec::new(),
+        QuadraticSolution::OneSolution(t) => vec![t],
+        QuadraticSolution::TwoSolutions(t1, t2) => vec![t1, t2],
+    }
+}
+
+// Where the local ray crosses the cap at height z, within `radius` of the axis.
+fn cap_hit(r: Ray, z: f32, radius: f32) -> Option<f32> {
+    if r.d.z.abs() < 1e-12 {
+        return None;
+    }
+    let t = (z - r.origin.z) / r.d.z;
+    let p = r.at(t);
+    (p.x * p.x + p.y * p.y <= radius * radius).then_some(t)
+}
+
+// Bounds a disk of `radius` around each end of the segment a-b; each axis
+// gets radius * sin of its angle to the segment.
+fn segment_bounding_box(a: Point, b: Point, radius: f32) -> Aabb {
+    let w = (b - a).unit();
+    let extent = |c: f32| radius * (1.0 - c * c).max(0.0).sqrt();
+    let e = Vec3 {
+        x: extent(w.x),
+        y: extent(w.y),
+        z: extent(w.z),
+    };
+    Aabb::new(vec::min(a, b) - e, vec::max(a, b) + e).padded()
+}
+
+fn validate_axis(a: Point, b: Point, path: &str, errors: &mut Vec<ValidationError>) {
+    validate_point(a, format!("{}.a", path), errors);
+    validate_point(b, format!("{}.b", path), errors);
+    if a == b {
+        errors.push(ValidationError::new(
+            format!("{}.b", path),
+            "must differ from a",
+        ));
+    }
+}
+
+fn validate_radius(r: f32, path: String, errors: &mut Vec<ValidationError>) {
+    if !(r > 0.0 && r.is_finite()) {
+        errors.push(ValidationError::new(path, "radius must be positive"));
+    }
+}
+
+fn closest(hits: Vec<HitRecord>, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    hits.into_iter()
+        .filter(|h| t_min <= h.t && h.t <= t_max)
+        .min_by(|a, b| a.t.total_cmp(&b.t))
+}
+
+// A cylinder of radius `r` around the segment from `a` to `b`, closed by
+// flat caps at both ends.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Cylinder {
+    pub a: Point,
+    pub b: Point,
+    pub r: f32,
+}
+
+// Hits on the side of a cylinder of radius `radius` around the local z axis,
+// between heights 0 and `height`.
+fn cylinder_side_hits(r: Ray, radius: f32, height: f32) -> impl Iterator<Item = (f32, Vec3)> {
+    let (o, d) = (r.origin, r.d);
+    let a = d.x * d.x + d.y * d.y;
+    let b = 2.0 * (o.x * d.x + o.y * d.y);
+    let c = o.x * o.x + o.y * o.y - radius * radius;
+    let roots = if a < 1e-12 {
+        Vec::new()
+    } else {
+        quadratic_roots(a, b, c)
+    };
+    roots.into_iter().filter_map(move |t| {
+        let p = r.at(t);
+        (0.0..=height).contains(&p.z).then_some((
+            t,
+            Vec3 {
+                x: p.x / radius,
+                y: p.y / radius,
+                z: 0.0,
+            },
+        ))
+    })
+}
+
+#[typetag::serde]
+impl Hittable for Cylinder {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        let frame = Frame::new(self.a, self.b - self.a);
+        let local = frame.ray_to_local(r);
+        let height = (self.b - self.a).length();
+        let down = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: -1.0,
+        };
+
+        let mut hits: Vec<(f32, Vec3)> = cylinder_side_hits(local, self.r, height).collect();
+        hits.extend(cap_hit(local, 0.0, self.r).map(|t| (t, down)));
+        hits.extend(cap_hit(local, height, self.r).map(|t| (t, -down)));
+        hits.into_iter()
+            .map(|(t, n)| planar_hit_record(r, t, frame.to_world(n)))
+            .collect()
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        closest(self.hit(r), t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(segment_bounding_box(self.a, self.b, self.r))
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_axis(self.a, self.b, path, errors);
+        validate_radius(self.r, format!("{}.r", path), errors);
+    }
+}
+
+// A cone with a base of radius `r` around `a`, closed by a flat cap, and its
+// apex at `b`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Cone {
+    pub a: Point,
+    pub b: Point,
+    pub r: f32,
+}
+
+#[typetag::serde]
+impl Hittable for Cone {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        let frame = Frame::new(self.a, self.b - self.a);
+        let local = frame.ray_to_local(r);
+        let height = (self.b - self.a).length();
+
+        // x^2 + y^2 = (k * (height - z))^2, the radius shrinking linearly to
+        // the apex.
+        let k2 = (self.r / height).powi(2);
+        let (o, d) = (local.origin, local.d);
+        let s = height - o.z;
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.y * d.y + k2 * s * d.z);
+        let c = o.x * o.x + o.y * o.y - k2 * s * s;
+
+        let mut hits: Vec<(f32, Vec3)> = quadratic_roots(a, b, c)
+            .into_iter()
+            .filter_map(|t| {
+                let p = local.at(t);
+                (0.0..=height).contains(&p.z).then(|| {
+                    let n = Vec3 {
+                        x: p.x,
+                        y: p.y,
+                        z: k2 * (height - p.z),
+                    };
+                    // The gradient vanishes at the apex itself.
+                    let n = if n.length_squared() > 0.0 {
+                        n.unit()
+                    } else {
+                        Vec3 {
+                            x: 0.0,
+                            y: 0.0,
+                            z: 1.0,
+                        }
+                    };
+                    (t, n)
+                })
+            })
+            .collect();
+        hits.extend(cap_hit(local, 0.0, self.r).map(|t| {
+            (
+                t,
+                Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -1.0,
+                },
+            )
+        }));
+        hits.into_iter()
+            .map(|(t, n)| planar_hit_record(r, t, frame.to_world(n)))
+            .collect()
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        closest(self.hit(r), t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(segment_bounding_box(self.a, self.b, self.r))
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_axis(self.a, self.b, path, errors);
+        validate_radius(self.r, format!("{}.r", path), errors);
+    }
+}
+
+// All points within `r` of the segment from `a` to `b`: a cylinder with
+// hemispheres for caps.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Capsule {
+    pub a: Point,
+    pub b: Point,
+    pub r: f32,
+}
+
+#[typetag::serde]
+impl Hittable for Capsule {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        let frame = Frame::new(self.a, self.b - self.a);
+        let local = frame.ray_to_local(r);
+        let height = (self.b - self.a).length();
+
+        let mut hits: Vec<(f32, Vec3)> = cylinder_side_hits(local, self.r, height).collect();
+        // Only the outer half of each end's sphere.
+        for (z, outer) in [(0.0, -1.0), (height, 1.0)] {
+            let center = Point { x: 0.0, y: 0.0, z };
+            let oc = local.origin - center;
+            let roots = quadratic_roots(
+                dot(local.d, local.d),
+                2.0 * dot(local.d, oc),
+                dot(oc, oc) - self.r * self.r,
+            );
+            hits.extend(roots.into_iter().filter_map(|t| {
+                let p = local.at(t);
+                ((p.z - z) * outer >= 0.0).then(|| (t, (p - center) / self.r))
+            }));
+        }
+        hits.into_iter()
+            .map(|(t, n)| planar_hit_record(r, t, frame.to_world(n)))
+            .collect()
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        closest(self.hit(r), t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let e = Vec3 {
+            x: self.r,
+            y: self.r,
+            z: self.r,
+        };
+        Some(Aabb::new(
+            vec::min(self.a, self.b) - e,
+            vec::max(self.a, self.b) + e,
+        ))
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_axis(self.a, self.b, path, errors);
+        validate_radius(self.r, format!("{}.r", path), errors);
+    }
+}
+
+// Real roots of the polynomial with coefficients `c`, lowest power first and
+// the last non-zero. Each root is bracketed between the roots of the
+// derivative, so the ones it has in common with it (double roots, where the
+// ray grazes a surface) can be missed.
+fn polynomial_roots(c: &[f64]) -> Vec<f64> {
+    let n = c.len() - 1;
+    if n == 1 {
+        return vec![-c[0] / c[1]];
+    }
+    let eval = |x: f64| c.iter().rev().fold(0.0, |acc, &ci| acc * x + ci);
+    let derivative: Vec<f64> = (1..=n).map(|i| c[i] * i as f64).collect();
+
+    // Cauchy's bound on the magnitude of the roots.
+    let bound = 1.0
+        + c[..n]
+            .iter()
+            .map(|ci| (ci / c[n]).abs())
+            .fold(0.0, f64::max);
+    let mut points = vec![-bound];
+    points.extend(
+        polynomial_roots(&derivative)
+            .into_iter()
+            .filter(|x| x.abs() < bound),
+    );
+    points.push(bound);
+    points.sort_by(f64::total_cmp);
+
+    points
+        .windows(2)
+        .filter_map(|w| {
+            let (mut lo, mut hi) = (w[0], w[1]);
+            let (f_lo, f_hi) = (eval(lo), eval(hi));
+            if f_lo == 0.0 {
+                return Some(lo);
+            }
+            if f_lo.signum() == f_hi.signum() {
+                return None;
+            }
+            for _ in 0..64 {
+                let mid = 0.5 * (lo + hi);
+                if mid == lo || mid == hi {
+                    break;
+                }
+                if eval(mid).signum() == f_lo.signum() {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            Some(0.5 * (lo + hi))
+        })
+        .collect()
+}
+
+// A ring around `o` in the plane facing `n`: points `minor` away from the
+// circle of radius `major`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Torus {
+    pub o: Point,
+    pub n: Vec3,
+    pub major: f32,
+    pub minor: f32,
+}
+
+#[typetag::serde]
+impl Hittable for Torus {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        let frame = Frame::new(self.o, self.n);
+        let local = frame.ray_to_local(r);
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2), in f64 along a unit
+        // direction starting from the point nearest the center, to keep the
+        // quartic well conditioned.
+        let length = local.d.length() as f64;
+        let t0 = -dot(local.origin, local.d) as f64 / (length * length);
+        let p = local.at(t0 as f32);
+        let d = local.d / length as f32;
+        let (ox, oy, oz) = (p.x as f64, p.y as f64, p.z as f64);
+        let (dx, dy, dz) = (d.x as f64, d.y as f64, d.z as f64);
+        let (major2, minor2) = ((self.major as f64).powi(2), (self.minor as f64).powi(2));
+
+        let od = ox * dx + oy * dy + oz * dz;
+        let k = ox * ox + oy * oy + oz * oz + major2 - minor2;
+        let coefficients = [
+            k * k - 4.0 * major2 * (ox * ox + oy * oy),
+            4.0 * od * k - 8.0 * major2 * (ox * dx + oy * dy),
+            4.0 * od * od + 2.0 * k - 4.0 * major2 * (dx * dx + dy * dy),
+            4.0 * od,
+            1.0,
+        ];
+
+        polynomial_roots(&coefficients)
+            .into_iter()
+            .map(|s| {
+                let t = (t0 + s / length) as f32;
+                let p = local.at(t);
+                // Away from the nearest point on the central circle.
+                let ring = (p.x * p.x + p.y * p.y).sqrt();
+                let center = match ring {
+                    0.0 => Default::default(),
+                    _ => Point {
+                        x: p.x / ring * self.major,
+                        y: p.y / ring * self.major,
+                        z: 0.0,
+                    },
+                };
+                planar_hit_record(r, t, frame.to_world((p - center).unit()))
+            })
+            .collect()
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        closest(self.hit(r), t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let n = self.n.unit();
+        let extent = |c: f32| {
+            (self.major + self.minor) * (1.0 - c * c).max(0.0).sqrt() + self.minor * c.abs()
+        };
+        let e = Vec3 {
+            x: extent(n.x),
+            y: extent(n.y),
+            z: extent(n.z),
+        };
+        Some(Aabb::new(self.o - e, self.o + e))
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_point(self.o, format!("{}.o", path), errors);
+        validate_normal(self.n, format!("{}.n", path), errors);
+        validate_radius(self.major, format!("{}.major", path), errors);
+        validate_radius(self.minor, format!("{}.minor", path), errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cylinder;
+    use super::Torus;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::vec::Point;
+    use crate::vec::Vec3;
+
+    #[test]
+    fn test_torus_and_cylinder_hits() {
+        let torus = Torus {
+            o: Default::default(),
+            n: Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 2.0,
+            },
+            major: 2.0,
+            minor: 0.5,
+        };
+        // Through the middle of the ring, crossing the tube twice.
+        let r = Ray {
+            origin: Point {
+                x: -5.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            d: Vec3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        };
+        let mut hits = torus.hit(r);
+        hits.sort_by(|a, b| a.t.total_cmp(&b.t));
+        let ts: Vec<f32> = hits.iter().map(|h| h.t).collect();
+        assert_eq!(ts.len(), 4);
+        for (t, expected) in ts.iter().zip([2.5, 3.5, 6.5, 7.5]) {
+            assert!((t - expected).abs() < 1e-4, "{:?}", ts);
+        }
+        let front: Vec<bool> = hits.iter().map(|h| h.front_face).collect();
+        assert_eq!(front, [true, false, true, false]);
+
+        // Along the axis, through both caps.
+        let cylinder = Cylinder {
+            a: Point {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            b: Point {
+                x: 0.0,
+                y: 3.0,
+                z: 0.0,
+            },
+            r: 0.5,
+        };
+        let r = Ray {
+            origin: Point {
+                x: 0.1,
+                y: 0.0,
+                z: 0.0,
+            },
+            d: Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        };
+        let h = cylinder.closest_hit(r, 0.0, f32::MAX).unwrap();
+        assert!((h.t - 1.0).abs() < 1e-5);
+        assert!(h.front_face);
+        assert!((h.n.y + 1.0).abs() < 1e-5);
+    }
+}
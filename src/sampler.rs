@@ -0,0 +1,451 @@
+use crate::rand::Rng;
+use crate::vec::dot;
+use crate::vec::Vec3;
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum SamplerKind {
+    // Independent uniform numbers.
+    Random,
+    // Jittered strata over the pixel's samples, Kensler's correlated
+    // multi-jittered sampling for 2D dimensions.
+    Stratified,
+    // Halton points, randomised per pixel by Cranley-Patterson rotation.
+    Halton,
+    // Owen-scrambled Sobol points, padded per dimension as in Burley,
+    // "Practical Hash-based Owen Scrambling".
+    #[default]
+    Sobol,
+    // Sobol points shifted per pixel by a blue noise mask, so the remaining
+    // error is spread as high frequency noise across the image.
+    BlueNoise,
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "random" => Ok(SamplerKind::Random),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "bluenoise" | "blue-noise" => Ok(SamplerKind::BlueNoise),
+            _ => Err(format!(
+                "unknown sampler '{}', expected one of random, stratified, halton, sobol, blue-noise",
+                s
+            )),
+        }
+    }
+}
+
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^ (x >> 16)
+}
+
+fn hash_combine(a: u32, b: u32) -> u32 {
+    hash(a ^ hash(b).wrapping_add(0x9e37_79b9))
+}
+
+fn to_float(x: u32) -> f32 {
+    (x >> 8) as f32 / (1u32 << 24) as f32
+}
+
+// The numbers for one sample of one pixel, handed out a dimension at a time:
+// the pixel position, then the lens, then whatever each bounce asks for.
+// Every dimension draws from its own randomised copy of the sequence, so
+// paths of any length can be sampled without correlation between bounces.
+pub struct Sampler {
+    kind: SamplerKind,
+    x: u32,
+    y: u32,
+    index: u32,
+    samples: u32,
+    // Seeds shared by every pixel and by this pixel alone.
+    global_seed: u32,
+    pixel_seed: u32,
+    dimension: u32,
+    rng: Rng,
+}
+
+impl Sampler {
+    // Sample `index` of `samples` for pixel (x, y).
+    pub fn new(kind: SamplerKind, seed: u64, x: usize, y: usize, index: u32, samples: u32) -> Self {
+        let (x, y) = (x as u32, y as u32);
+        let global_seed = hash_combine(seed as u32, (seed >> 32) as u32);
+        Self {
+            kind,
+            x,
+            y,
+            index,
+            samples: samples.max(1),
+            global_seed,
+            pixel_seed: hash_combine(hash_combine(global_seed, x), y),
+            dimension: 0,
+            rng: Rng::for_sample(seed, (y as u64) << 32 | x as u64, index as u64),
+        }
+    }
+
+    fn next_dimension(&mut self) -> u32 {
+        self.dimension += 1;
+        self.dimension - 1
+    }
+
+    pub fn get_1d(&mut self) -> f32 {
+        let dimension = self.next_dimension();
+        let seed = hash_combine(self.pixel_seed, dimension);
+        match self.kind {
+            SamplerKind::Random => self.rng.float(),
+            SamplerKind::Stratified => {
+                let (round, i) = (self.index / self.samples, self.index % self.samples);
+                let seed = hash_combine(seed, round);
+                let stratum = permute(i, self.samples, seed);
+                (stratum as f32 + randfloat(i, seed ^ 0xa511_e9b3)) / self.samples as f32
+            }
+            SamplerKind::Halton => self.halton(dimension, seed),
+            SamplerKind::Sobol => sobol_owen_1d(self.index, seed),
+            SamplerKind::BlueNoise => self.blue_noise(0, dimension),
+        }
+        .min(ONE_MINUS_EPSILON)
+    }
+
+    pub fn get_2d(&mut self) -> (f32, f32) {
+        if self.kind == SamplerKind::Halton {
+            return (self.get_1d(), self.get_1d());
+        }
+        let dimension = self.next_dimension();
+        self.dimension += 1;
+        let seed = hash_combine(self.pixel_seed, dimension);
+        let (u, v) = match self.kind {
+            SamplerKind::Random => (self.rng.float(), self.rng.float()),
+            SamplerKind::Stratified => {
+                let (round, i) = (self.index / self.samples, self.index % self.samples);
+                correlated_multi_jitter(i, self.samples, hash_combine(seed, round))
+            }
+            SamplerKind::Sobol => sobol_owen_2d(self.index, seed),
+            _ => (self.blue_noise(0, dimension), self.blue_noise(1, dimension)),
+        };
+        (u.min(ONE_MINUS_EPSILON), v.min(ONE_MINUS_EPSILON))
+    }
+
+    fn halton(&mut self, dimension: u32, seed: u32) -> f32 {
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => {
+                let shift = randfloat(dimension, seed) as f64;
+                ((radical_inverse(self.index, base) + shift) % 1.0) as f32
+            }
+            None => self.rng.float(),
+        }
+    }
+
+    // One coordinate of a Sobol point shared by all pixels, toroidally
+    // shifted by the mask value at this pixel. Each dimension reads the mask
+    // at its own offset and uses its own shuffle of the sequence.
+    fn blue_noise(&self, axis: u32, dimension: u32) -> f32 {
+        let seed = hash_combine(self.global_seed, dimension);
+        let i = nested_uniform_scramble(self.index, seed);
+        let point = to_float(sobol(i, axis));
+
+        let offset = hash_combine(seed, axis);
+        let mx = (self.x + (offset & 0xffff)) as usize % BLUE_NOISE_SIZE;
+        let my = (self.y + (offset >> 16)) as usize % BLUE_NOISE_SIZE;
+        (point + blue_noise_mask()[my * BLUE_NOISE_SIZE + mx]) % 1.0
+    }
+
+    // Uniform over the unit disk in the xy plane, by Shirley and Chiu's
+    // concentric mapping.
+    pub fn in_disk(&mut self) -> Vec3 {
+        let (u, v) = self.get_2d();
+        let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Default::default();
+        }
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, PI / 4.0 * (b / a))
+        } else {
+            (b, PI / 2.0 - PI / 4.0 * (a / b))
+        };
+        Vec3 {
+            x: r * theta.cos(),
+            y: r * theta.sin(),
+            z: 0.0,
+        }
+    }
+
+    pub fn on_sphere(&mut self) -> Vec3 {
+        let (u, v) = self.get_2d();
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        Vec3 {
+            x: r * phi.cos(),
+            y: r * phi.sin(),
+            z,
+        }
+    }
+
+    pub fn in_sphere(&mut self) -> Vec3 {
+        self.on_sphere() * self.get_1d().cbrt()
+    }
+
+    pub fn in_hemisphere(&mut self, n: Vec3) -> Vec3 {
+        let v = self.on_sphere();
+        match dot(v, n) < 0.0 {
+            true => -v,
+            _ => v,
+        }
+    }
+}
+
+// Kensler, "Correlated Multi-Jittered Sampling": a random permutation of
+// 0..l for each pattern `p`, computed without storing it.
+fn permute(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            return (i.wrapping_add(p)) % l;
+        }
+    }
+}
+
+fn randfloat(mut i: u32, p: u32) -> f32 {
+    i ^= p;
+    i ^= i >> 17;
+    i ^= i >> 10;
+    i = i.wrapping_mul(0xb365_34e5);
+    i ^= i >> 12;
+    i ^= i >> 21;
+    i = i.wrapping_mul(0x93fc_4795);
+    i ^= 0xdf6e_307f;
+    i ^= i >> 17;
+    i = i.wrapping_mul(1 | p >> 18);
+    to_float(i)
+}
+
+fn correlated_multi_jitter(s: u32, n: u32, p: u32) -> (f32, f32) {
+    let m = (n as f32).sqrt() as u32;
+    let rows = n.div_ceil(m);
+    let s = permute(s, n, p.wrapping_mul(0x5163_3e2d));
+    let sx = permute(s % m, m, p.wrapping_mul(0x68bc_21eb));
+    let sy = permute(s / m, rows, p.wrapping_mul(0x02e5_be93));
+    let jx = randfloat(s, p.wrapping_mul(0x967a_889b));
+    let jy = randfloat(s, p.wrapping_mul(0x368c_c8b7));
+    (
+        (sx as f32 + (sy as f32 + jx) / rows as f32) / m as f32,
+        (s as f32 + jy) / n as f32,
+    )
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+fn radical_inverse(mut i: u32, base: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let (mut digits, mut scale) = (0.0, inverse_base);
+    while i > 0 {
+        digits += (i % base) as f64 * scale;
+        i /= base;
+        scale *= inverse_base;
+    }
+    digits
+}
+
+// The first two Sobol dimensions as 32-bit fractions. Higher dimensions are
+// not needed, as every dimension pair gets its own shuffle and scramble.
+fn sobol(index: u32, dimension: u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    let (mut v, mut result) = (1u32 << 31, 0);
+    let mut i = index;
+    while i != 0 {
+        if i & 1 != 0 {
+            result ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn sobol_owen_1d(index: u32, seed: u32) -> f32 {
+    let i = nested_uniform_scramble(index, seed);
+    to_float(nested_uniform_scramble(sobol(i, 0), hash(seed)))
+}
+
+fn sobol_owen_2d(index: u32, seed: u32) -> (f32, f32) {
+    let i = nested_uniform_scramble(index, seed);
+    (
+        to_float(nested_uniform_scramble(sobol(i, 0), hash_combine(seed, 0))),
+        to_float(nested_uniform_scramble(sobol(i, 1), hash_combine(seed, 1))),
+    )
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+// A tileable mask of values in [0, 1) made with Ulichney's void-and-cluster
+// method, computed once on first use.
+fn blue_noise_mask() -> &'static [f32] {
+    static MASK: OnceLock<Vec<f32>> = OnceLock::new();
+    MASK.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE, 1.9))
+}
+
+fn void_and_cluster(size: usize, sigma: f32) -> Vec<f32> {
+    let n = size * size;
+    let kernel: Vec<f32> = (0..n)
+        .map(|i| {
+            let wrap = |d: usize| d.min(size - d) as f32;
+            let (dx, dy) = (wrap(i % size), wrap(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+
+    // Energy of each pixel due to the points set so far, on a torus.
+    let mut energy = vec![0.0f32; n];
+    let update = |energy: &mut Vec<f32>, p: usize, sign: f32| {
+        let (px, py) = (p % size, p / size);
+        for (i, e) in energy.iter_mut().enumerate() {
+            let dx = (i % size + size - px) % size;
+            let dy = (i / size + size - py) % size;
+            *e += sign * kernel[dy * size + dx];
+        }
+    };
+    let extreme = |energy: &[f32], pattern: &[bool], set: bool, max: bool| -> usize {
+        let candidates = (0..n).filter(|&i| pattern[i] == set);
+        let key = |&i: &usize| if max { energy[i] } else { -energy[i] };
+        candidates.max_by(|a, b| key(a).total_cmp(&key(b))).unwrap()
+    };
+
+    // A random initial pattern, relaxed by moving the point in the tightest
+    // cluster to the largest void until that no longer changes anything.
+    let mut rng = Rng::new(0, 0);
+    let mut pattern = vec![false; n];
+    let ones = n / 10;
+    let mut placed = 0;
+    while placed < ones {
+        let i = (rng.next_u32() as usize) % n;
+        if !pattern[i] {
+            pattern[i] = true;
+            update(&mut energy, i, 1.0);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = extreme(&energy, &pattern, true, true);
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = extreme(&energy, &pattern, false, false);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize; n];
+    // Rank the initial points by removing tightest clusters first.
+    let (mut removed, mut removed_energy) = (pattern.clone(), energy.clone());
+    for r in (0..ones).rev() {
+        let cluster = extreme(&removed_energy, &removed, true, true);
+        removed[cluster] = false;
+        update(&mut removed_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+    // Then fill the largest voids. With a linear filter, the tightest cluster
+    // of empty pixels in the second half is the same as the largest void.
+    for r in ones..n {
+        let void = extreme(&energy, &pattern, false, false);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.into_iter()
+        .map(|r| (r as f32 + 0.5) / n as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sampler;
+    use super::SamplerKind;
+
+    fn rms_error(kind: SamplerKind) -> f32 {
+        // Estimates the integral of u * v over the unit square, 1/4, once per pixel.
+        let mut squared_error = 0.0;
+        for pixel in 0..64 {
+            let mut sum = 0.0;
+            for i in 0..64 {
+                let mut sampler = Sampler::new(kind, 1, pixel, 0, i, 64);
+                sampler.get_1d();
+                let (u, v) = sampler.get_2d();
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                sum += u * v;
+            }
+            squared_error += (sum / 64.0 - 0.25f32).powi(2);
+        }
+        (squared_error / 64.0).sqrt()
+    }
+
+    #[test]
+    fn test_samplers_beat_random() {
+        let random = rms_error(SamplerKind::Random);
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+            SamplerKind::BlueNoise,
+        ] {
+            let error = rms_error(kind);
+            assert!(error < random / 2.0, "{:?}: {} vs {}", kind, error, random);
+        }
+    }
+}
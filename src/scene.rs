@@ -1,10 +1,175 @@
+use crate::background::Background;
 use crate::camera::CameraConfig;
+use crate::error::Error;
+use crate::error::Result;
+use crate::error::ValidationError;
 use crate::hittable::World;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
 #[derive(Serialize, Deserialize)]
 pub struct Scene {
     pub camera_config: CameraConfig,
     pub world: World,
+    #[serde(default)]
+    pub background: Background,
+    #[serde(skip)]
+    assets: Vec<PathBuf>,
+}
+
+impl Scene {
+    pub fn new(camera_config: CameraConfig, world: World, background: Background) -> Self {
+        Self {
+            camera_config,
+            world,
+            background,
+            assets: Vec::new(),
+        }
+    }
+
+    // Reads a scene and loads the meshes and images it refers to, which are
+    // resolved relative to the scene file, then validates the result so the
+    // imported meshes are checked too.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let mut scene: Scene = deserialize_from_file(path)?;
+        let scene_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut assets = Vec::new();
+        scene.world.load_meshes(scene_dir, &mut assets)?;
+        scene.background.load(scene_dir, &mut assets)?;
+        scene.assets = assets;
+        let errors = scene.validate();
+        if !errors.is_empty() {
+            return Err(Error::Invalid {
+                path: Some(path.to_path_buf()),
+                errors,
+            });
+        }
+        Ok(scene)
+    }
+
+    // The files `from_file` read besides the scene file itself.
+    pub fn assets(&self) -> &[PathBuf] {
+        &self.assets
+    }
+
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        self.camera_config.validate("camera_config", &mut errors);
+        self.world.validate("world", &mut errors);
+        self.background.validate("background", &mut errors);
+        errors
+    }
+}
+
+pub fn deserialize_from_file<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let file = File::open(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    deserialize(path, BufReader::new(file))
+}
+
+// Errors name `path` as where the JSON came from.
+fn deserialize<T: DeserializeOwned, R: Read>(path: &Path, reader: R) -> Result<T> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|e| Error::Json {
+        path: path.to_path_buf(),
+        field: e.path().to_string(),
+        source: e.into_inner(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::deserialize;
+    use super::Scene;
+    use crate::error::Error;
+    use crate::error::ValidationError;
+
+    use std::path::Path;
+
+    #[test]
+    fn test_json_error_location() {
+        let json = r#"{
+  "camera_config": {
+    "lookfrom": {"x": 0, "y": 0, "z": 0},
+    "lookat": {"x": 0, "y": 0, "z": -1},
+    "vup": {"x": 0, "y": 1, "z": 0},
+    "vfov": 90, "aspect_ratio": 1, "aperture": 0, "focus_dist": 1
+  },
+  "world": {"objects": [
+    {
+      "material": {"type": "Lambertian", "albedo": {"x": 1, "y": 1, "z": 1}},
+      "hittable": {"type": "Sphere", "o": {"x": 0, "y": 0, "z": -2}, "r": 1}
+    },
+    {
+      "material": {"type": "Plastic", "albedo": {"x": 1, "y": 1, "z": 1}},
+      "hittable": {"type": "Sphere", "o": {"x": 0, "y": 0, "z": -4}, "r": 1}
+    }
+  ]}
+}"#;
+        let e = match deserialize::<Scene, _>(Path::new("scene.json"), json.as_bytes()) {
+            Err(e) => e,
+            Ok(_) => panic!("expected an error"),
+        };
+        match &e {
+            Error::Json { field, .. } => assert_eq!(field, "world.objects[1].material.type"),
+            _ => panic!("expected a JSON error, got {}", e),
+        }
+        assert!(
+            e.to_string().starts_with(
+                "scene.json:14:36: at `world.objects[1].material.type`: unknown variant `Plastic`"
+            ),
+            "{}",
+            e
+        );
+        assert_eq!(e.exit_code(), 65);
+    }
+
+    #[test]
+    fn test_validation_errors_are_collected() {
+        let scene: Scene = serde_json::from_str(
+            r#"{
+                "camera_config": {
+                    "lookfrom": {"x": 0, "y": 0, "z": 0},
+                    "lookat": {"x": 0, "y": -1, "z": 0},
+                    "vup": {"x": 0, "y": 2, "z": 0},
+                    "vfov": 90, "aspect_ratio": 1, "aperture": 0, "focus_dist": 1
+                },
+                "world": {"objects": [
+                    {
+                        "material": {"type": "Lambertian", "albedo": {"x": 1, "y": 1, "z": 1}},
+                        "hittable": {"type": "Sphere", "o": {"x": 0, "y": 0, "z": -2}, "r": 0}
+                    },
+                    {
+                        "material": {"type": "Dielectric", "ir": -1.5},
+                        "hittable": {"type": "Sphere", "o": {"x": 0, "y": 0, "z": -4}, "r": 1}
+                    }
+                ]}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            scene.validate(),
+            [
+                ValidationError::new(
+                    "camera_config.vup",
+                    "must not be parallel to the viewing direction"
+                ),
+                ValidationError::new(
+                    "world.objects[0].hittable.r",
+                    "radius must be finite and non-zero"
+                ),
+                ValidationError::new(
+                    "world.objects[1].material.ir",
+                    "refractive index must be positive"
+                ),
+            ]
+        );
+    }
 }
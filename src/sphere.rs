@@ -1,10 +1,19 @@
+use crate::aabb::Aabb;
+use crate::error::ValidationError;
+use crate::hittable::area_to_solid_angle_pdf;
+use crate::hittable::validate_point;
 use crate::hittable::HitRecord;
 use crate::hittable::Hittable;
+use crate::hittable::LightSample;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::dot;
+use crate::vec::orthonormal_basis;
 use crate::vec::Point;
+use crate::vec::Vec3;
 
 use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
 use typetag;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -13,14 +22,14 @@ pub struct Sphere {
     pub r: f32,
 }
 
-enum QuadraticSolution {
+pub(crate) enum QuadraticSolution {
     NoSolution,
     OneSolution(f32),
     TwoSolutions(f32, f32),
 }
 
 // a*x^2 + b*x + c = 0
-fn solve_quadratic(a: f32, b: f32, c: f32) -> QuadraticSolution {
+pub(crate) fn solve_quadratic(a: f32, b: f32, c: f32) -> QuadraticSolution {
     let d = b * b - 4.0 * a * c;
     if d < 0.0 {
         return QuadraticSolution::NoSolution;
@@ -52,33 +61,111 @@ fn intersect_sphere_ray(s: Sphere, r: Ray) -> RaySphereIntersection {
     }
 }
 
+impl Sphere {
+    fn area(&self) -> f32 {
+        4.0 * PI * self.r * self.r
+    }
+
+    // 1 - cos of the half-angle of the cone the sphere subtends from `origin`,
+    // or None when the origin is inside.
+    fn subtended_cone(&self, origin: Point) -> Option<f32> {
+        let r2 = self.r * self.r;
+        let d2 = (self.o - origin).length_squared();
+        if d2 <= r2 {
+            return None;
+        }
+        let sin2_max = r2 / d2;
+        Some(sin2_max / (1.0 + (1.0 - sin2_max).sqrt()))
+    }
+
+    fn hit_record(&self, r: Ray, t: f32) -> HitRecord {
+        let p = r.at(t);
+        let outward_n = (p - self.o) / self.r;
+        let front_face = dot(outward_n, r.origin - p) >= 0.0;
+        let n = if front_face { outward_n } else { -outward_n };
+        HitRecord {
+            t,
+            p,
+            n,
+            front_face,
+        }
+    }
+}
+
 #[typetag::serde]
 impl Hittable for Sphere {
     fn hit(&self, r: Ray) -> Vec<HitRecord> {
-        let mut hits: Vec<HitRecord> = Vec::new();
-
-        let get_hit_record = |t: f32| {
-            let p = r.at(t);
-            let outward_n = (p - self.o) / self.r;
-            let front_face = dot(outward_n, r.origin - p) >= 0.0;
-            let n = if front_face { outward_n } else { -outward_n };
-            HitRecord {
-                t,
-                p,
-                n,
-                front_face,
+        match intersect_sphere_ray(*self, r) {
+            RaySphereIntersection::OnePoint(t) => vec![self.hit_record(r, t)],
+            RaySphereIntersection::TwoPoints(t1, t2) => {
+                vec![self.hit_record(r, t1), self.hit_record(r, t2)]
             }
+            RaySphereIntersection::NoIntersection => Vec::new(),
+        }
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let in_range = |t: f32| t_min <= t && t <= t_max;
+        let t = match intersect_sphere_ray(*self, r) {
+            RaySphereIntersection::OnePoint(t) => Some(t).filter(|&t| in_range(t)),
+            // Roots come out ordered for a > 0, which dot(d, d) always is.
+            RaySphereIntersection::TwoPoints(t1, t2) => [t1, t2].into_iter().find(|&t| in_range(t)),
+            RaySphereIntersection::NoIntersection => None,
         };
+        t.map(|t| self.hit_record(r, t))
+    }
 
-        match intersect_sphere_ray(*self, r) {
-            RaySphereIntersection::OnePoint(t) => hits.push(get_hit_record(t)),
-            RaySphereIntersection::TwoPoints(t1, t2) => {
-                hits.push(get_hit_record(t1));
-                hits.push(get_hit_record(t2));
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3 {
+            x: self.r.abs(),
+            y: self.r.abs(),
+            z: self.r.abs(),
+        };
+        Some(Aabb::new(self.o - r, self.o + r))
+    }
+
+    // Uniform over the visible cone from outside, uniform by area from inside.
+    fn sample(&self, origin: Point, sampler: &mut Sampler) -> Option<LightSample> {
+        let one_minus_cos_max = match self.subtended_cone(origin) {
+            Some(c) => c,
+            None => {
+                let n = sampler.on_sphere();
+                return LightSample::from_area(origin, self.o + n * self.r.abs(), n, self.area());
             }
-            _ => {}
+        };
+
+        let w = (self.o - origin).unit();
+        let (u, v) = orthonormal_basis(w);
+        let (s, t) = sampler.get_2d();
+        let cos_theta = 1.0 - s * one_minus_cos_max;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * t;
+        let d = u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * cos_theta;
+
+        let h = self.closest_hit(Ray { origin, d }, 0.0, f32::MAX)?;
+        Some(LightSample::new(
+            origin,
+            h.p,
+            (h.p - self.o) / self.r,
+            1.0 / (2.0 * PI * one_minus_cos_max),
+        ))
+    }
+
+    fn sample_pdf(&self, origin: Point, h: HitRecord) -> f32 {
+        match self.subtended_cone(origin) {
+            Some(one_minus_cos_max) => 1.0 / (2.0 * PI * one_minus_cos_max),
+            None => area_to_solid_angle_pdf(origin, h.p, h.n, self.area()),
         }
+    }
 
-        hits
+    // Negative radii are allowed; they flip the normals, e.g. for hollow glass.
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_point(self.o, format!("{}.o", path), errors);
+        if self.r == 0.0 || !self.r.is_finite() {
+            errors.push(ValidationError::new(
+                format!("{}.r", path),
+                "radius must be finite and non-zero",
+            ));
+        }
     }
 }
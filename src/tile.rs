@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::str::FromStr;
+
+// The order tiles are handed out to worker threads in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TileOrder {
+    // Left to right, top to bottom.
+    Scanline,
+    // Outwards from the center of the image, which usually matters most.
+    Spiral,
+    // Along a Hilbert curve, so consecutive tiles are neighbours and share
+    // more of the scene in the caches.
+    #[default]
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!(
+                "unknown tile order '{}', expected one of scanline, spiral, hilbert",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub x: Range<usize>,
+    pub y: Range<usize>,
+}
+
+// Position of (x, y) along the Hilbert curve filling an n x n grid, n a power
+// of two.
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as usize;
+        let ry = (y & s > 0) as usize;
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            } else {
+                x &= s - 1;
+                y &= s - 1;
+            }
+            std::mem::swap(&mut x, &mut y);
+        } else {
+            x &= s - 1;
+            y &= s - 1;
+        }
+        s /= 2;
+    }
+    d
+}
+
+// Splits a width x height image into tiles of at most size x size pixels.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    let (columns, rows) = (width.div_ceil(size), height.div_ceil(size));
+    let mut cells: Vec<(usize, usize)> = (0..rows)
+        .flat_map(|r| (0..columns).map(move |c| (c, r)))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            // By ring around the central tile, then by angle within the ring.
+            let (cx, cy) = ((columns - 1) as f32 / 2.0, (rows - 1) as f32 / 2.0);
+            let key = |&(c, r): &(usize, usize)| {
+                let (dx, dy) = (c as f32 - cx, r as f32 - cy);
+                (dx.abs().max(dy.abs()), dy.atan2(dx))
+            };
+            cells.sort_by(|a, b| {
+                let (ring_a, angle_a) = key(a);
+                let (ring_b, angle_b) = key(b);
+                ring_a.total_cmp(&ring_b).then(angle_a.total_cmp(&angle_b))
+            });
+        }
+        TileOrder::Hilbert => {
+            let n = columns.max(rows).next_power_of_two();
+            cells.sort_by_key(|&(c, r)| hilbert_index(n, c, r));
+        }
+    }
+
+    cells
+        .into_iter()
+        .map(|(c, r)| Tile {
+            x: c * size..((c + 1) * size).min(width),
+            y: r * size..((r + 1) * size).min(height),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tiles;
+    use super::TileOrder;
+
+    #[test]
+    fn test_tiles_cover_image_once() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let mut covered = vec![0; 50 * 30];
+            let tiles = tiles(50, 30, 16, order);
+            assert_eq!(tiles.len(), 4 * 2);
+            for tile in &tiles {
+                for y in tile.y.clone() {
+                    for x in tile.x.clone() {
+                        covered[y * 50 + x] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&c| c == 1), "{:?}", order);
+        }
+
+        // Consecutive tiles along the Hilbert curve are neighbours.
+        let hilbert = tiles(64, 64, 16, TileOrder::Hilbert);
+        for pair in hilbert.windows(2) {
+            let dx = pair[0].x.start.abs_diff(pair[1].x.start);
+            let dy = pair[0].y.start.abs_diff(pair[1].y.start);
+            assert_eq!(dx + dy, 16);
+        }
+    }
+}
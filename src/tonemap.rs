@@ -0,0 +1,107 @@
+use crate::error::ValidationError;
+use crate::vec::Color;
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ToneMapOperator {
+    #[default]
+    Clamp,
+    Reinhard,
+    Aces,
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clamp" => Ok(ToneMapOperator::Clamp),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "aces" => Ok(ToneMapOperator::Aces),
+            _ => Err(format!(
+                "unknown tone mapping operator '{}', expected one of clamp, reinhard, aces",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct PostProcessConfig {
+    // In stops, so 1.0 doubles the brightness.
+    #[serde(default)]
+    pub exposure: f32,
+    #[serde(default)]
+    pub tone_mapping: ToneMapOperator,
+    // None applies the sRGB transfer curve, Some(g) a plain 1/g power curve.
+    #[serde(default)]
+    pub gamma: Option<f32>,
+}
+
+fn map_channel(op: ToneMapOperator, x: f32) -> f32 {
+    let x = x.max(0.0);
+    match op {
+        ToneMapOperator::Clamp => x.min(1.0),
+        ToneMapOperator::Reinhard => x / (1.0 + x),
+        // Narkowicz's fit of the ACES filmic curve.
+        ToneMapOperator::Aces => {
+            ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+        }
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl PostProcessConfig {
+    // Scales linear radiance by the exposure, for HDR outputs.
+    pub fn expose(&self, c: Color) -> Color {
+        c * self.exposure.exp2()
+    }
+
+    // Full pipeline for display outputs: exposure, tone mapping, then gamma.
+    pub fn to_display(&self, c: Color) -> Color {
+        let c = self.expose(c);
+        let encode = |x: f32| {
+            let x = map_channel(self.tone_mapping, x);
+            match self.gamma {
+                None => linear_to_srgb(x),
+                Some(g) => x.powf(1.0 / g),
+            }
+        };
+        Color {
+            x: encode(c.x),
+            y: encode(c.y),
+            z: encode(c.z),
+        }
+    }
+
+    pub fn to_rgb8(&self, c: Color) -> [u8; 3] {
+        let c = self.to_display(c);
+        [c.x, c.y, c.z].map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+
+    pub fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if !self.exposure.is_finite() {
+            errors.push(ValidationError::new(
+                format!("{}.exposure", path),
+                "must be finite",
+            ));
+        }
+        if let Some(gamma) = self.gamma {
+            if !(gamma > 0.0 && gamma.is_finite()) {
+                errors.push(ValidationError::new(
+                    format!("{}.gamma", path),
+                    "must be positive",
+                ));
+            }
+        }
+    }
+}
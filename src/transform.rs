@@ -0,0 +1,317 @@
+use crate::aabb::Aabb;
+use crate::error::ValidationError;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::hittable::LightSample;
+use crate::mat::Mat4;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec;
+use crate::vec::dot;
+use crate::vec::Point;
+use crate::vec::Vec3;
+
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+// Angles are in degrees.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Rotation {
+    AxisAngle { axis: Vec3, degrees: f32 },
+    // Around x, then y, then z.
+    Euler(Vec3),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Scale {
+    Uniform(f32),
+    PerAxis(Vec3),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransformStep {
+    Translate(Vec3),
+    Rotate(Rotation),
+    Scale(Scale),
+}
+
+impl TransformStep {
+    pub fn matrix(&self) -> Mat4 {
+        let axis = |x, y, z| Vec3 { x, y, z };
+        match *self {
+            TransformStep::Translate(t) => Mat4::translate(t),
+            TransformStep::Rotate(Rotation::AxisAngle { axis, degrees }) => {
+                Mat4::rotate(axis, degrees)
+            }
+            TransformStep::Rotate(Rotation::Euler(e)) => {
+                Mat4::rotate(axis(0.0, 0.0, 1.0), e.z)
+                    * Mat4::rotate(axis(0.0, 1.0, 0.0), e.y)
+                    * Mat4::rotate(axis(1.0, 0.0, 0.0), e.x)
+            }
+            TransformStep::Scale(Scale::Uniform(s)) => Mat4::scale(axis(s, s, s)),
+            TransformStep::Scale(Scale::PerAxis(s)) => Mat4::scale(s),
+        }
+    }
+
+    pub(crate) fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        let mut error = |field: &str, message: &str| {
+            errors.push(ValidationError::new(format!("{}.{}", path, field), message));
+        };
+        match *self {
+            TransformStep::Translate(t) => {
+                if !t.is_finite() {
+                    error("translate", "must be finite");
+                }
+            }
+            TransformStep::Rotate(Rotation::AxisAngle { axis, degrees }) => {
+                if !axis.is_finite() || axis.length_squared() == 0.0 {
+                    error("rotate.axis", "must be finite and non-zero");
+                }
+                if !degrees.is_finite() {
+                    error("rotate.degrees", "must be finite");
+                }
+            }
+            TransformStep::Rotate(Rotation::Euler(e)) => {
+                if !e.is_finite() {
+                    error("rotate", "must be finite");
+                }
+            }
+            TransformStep::Scale(s) => {
+                let s = match s {
+                    Scale::Uniform(s) => Vec3 { x: s, y: s, z: s },
+                    Scale::PerAxis(s) => s,
+                };
+                if !s.is_finite() || s.x == 0.0 || s.y == 0.0 || s.z == 0.0 {
+                    error("scale", "must be finite and non-zero");
+                }
+            }
+        }
+    }
+}
+
+pub(crate) struct Matrices {
+    to_world: Mat4,
+    to_object: Mat4,
+    // Inverse transpose, which keeps normals perpendicular to the surface.
+    normal_to_world: Mat4,
+    // Rotation, translation and uniform scaling keep solid angles, so light
+    // sampling can be done in object space.
+    similarity: bool,
+}
+
+impl Matrices {
+    // For the steps applied in order.
+    pub(crate) fn new(steps: &[TransformStep]) -> Self {
+        let to_world = steps
+            .iter()
+            .fold(Mat4::identity(), |m, step| step.matrix() * m);
+        // Singular matrices are reported by validate.
+        let to_object = to_world.inverse().unwrap_or_default();
+        let [x, y, z] = to_world.axes();
+        let scale = x.length_squared();
+        let close = |a: f32, b: f32| (a - b).abs() <= 1e-4 * scale;
+        Self {
+            to_world,
+            to_object,
+            normal_to_world: to_object.transpose(),
+            similarity: close(y.length_squared(), scale)
+                && close(z.length_squared(), scale)
+                && close(dot(x, y), 0.0)
+                && close(dot(y, z), 0.0)
+                && close(dot(z, x), 0.0),
+        }
+    }
+
+    // The direction is left unnormalized, so hit distances are the same in
+    // both spaces.
+    pub(crate) fn ray_to_object(&self, r: Ray) -> Ray {
+        Ray {
+            origin: self.to_object.transform_point(r.origin),
+            d: self.to_object.transform_vector(r.d),
+        }
+    }
+
+    // A hit found with `ray_to_object(r)`.
+    pub(crate) fn hit_to_world(&self, r: Ray, h: HitRecord) -> HitRecord {
+        HitRecord {
+            p: r.at(h.t),
+            n: self.normal_to_world.transform_vector(h.n).unit(),
+            ..h
+        }
+    }
+
+    pub(crate) fn bounding_box_to_world(&self, bbox: Aabb) -> Aabb {
+        let corner = |i: usize| Point {
+            x: if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+            y: if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+            z: if i & 4 == 0 { bbox.min.z } else { bbox.max.z },
+        };
+        let first = self.to_world.transform_point(corner(0));
+        let (min, max) = (1..8)
+            .map(|i| self.to_world.transform_point(corner(i)))
+            .fold((first, first), |(min, max), p| {
+                (vec::min(min, p), vec::max(max, p))
+            });
+        Aabb::new(min, max)
+    }
+}
+
+// Places the wrapped hittable with the steps applied in order, e.g. scale,
+// then rotate, then translate.
+#[derive(Serialize, Deserialize)]
+pub struct Transform {
+    pub steps: Vec<TransformStep>,
+    pub hittable: Box<dyn Hittable>,
+    #[serde(skip)]
+    matrices: OnceLock<Matrices>,
+}
+
+impl Transform {
+    pub fn new(steps: Vec<TransformStep>, hittable: Box<dyn Hittable>) -> Self {
+        Self {
+            steps,
+            hittable,
+            matrices: OnceLock::new(),
+        }
+    }
+
+    fn matrices(&self) -> &Matrices {
+        self.matrices.get_or_init(|| Matrices::new(&self.steps))
+    }
+}
+
+#[typetag::serde]
+impl Hittable for Transform {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        let matrices = self.matrices();
+        self.hittable
+            .hit(matrices.ray_to_object(r))
+            .into_iter()
+            .map(|h| matrices.hit_to_world(r, h))
+            .collect()
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let matrices = self.matrices();
+        self.hittable
+            .closest_hit(matrices.ray_to_object(r), t_min, t_max)
+            .map(|h| matrices.hit_to_world(r, h))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = self.hittable.bounding_box()?;
+        Some(self.matrices().bounding_box_to_world(bbox))
+    }
+
+    fn sample(&self, origin: Point, sampler: &mut Sampler) -> Option<LightSample> {
+        let matrices = self.matrices();
+        if !matrices.similarity {
+            return None;
+        }
+        let local_origin = matrices.to_object.transform_point(origin);
+        self.hittable
+            .sample(local_origin, sampler)
+            .map(|s| LightSample {
+                hit_record: HitRecord {
+                    p: matrices.to_world.transform_point(s.hit_record.p),
+                    n: matrices
+                        .normal_to_world
+                        .transform_vector(s.hit_record.n)
+                        .unit(),
+                    ..s.hit_record
+                },
+                pdf: s.pdf,
+            })
+    }
+
+    fn sample_pdf(&self, origin: Point, h: HitRecord) -> f32 {
+        let matrices = self.matrices();
+        if !matrices.similarity {
+            return 0.0;
+        }
+        let local = HitRecord {
+            p: matrices.to_object.transform_point(h.p),
+            n: matrices.to_world.transpose().transform_vector(h.n).unit(),
+            ..h
+        };
+        self.hittable
+            .sample_pdf(matrices.to_object.transform_point(origin), local)
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (i, step) in self.steps.iter().enumerate() {
+            step.validate(&format!("{}.steps[{}]", path, i), errors);
+        }
+        self.hittable
+            .validate(&format!("{}.hittable", path), errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rotation;
+    use super::Scale;
+    use super::Transform;
+    use super::TransformStep;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::vec::Point;
+    use crate::vec::Vec3;
+
+    #[test]
+    fn test_transformed_sphere() {
+        let sphere = || {
+            Box::new(Sphere {
+                o: Default::default(),
+                r: 1.0,
+            })
+        };
+        let down_z = |x: f32| Ray {
+            origin: Point { x, y: 0.0, z: 5.0 },
+            d: Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -1.0,
+            },
+        };
+
+        let moved = Transform::new(
+            vec![
+                TransformStep::Scale(Scale::Uniform(2.0)),
+                TransformStep::Rotate(Rotation::Euler(Vec3 {
+                    x: 30.0,
+                    y: 60.0,
+                    z: 90.0,
+                })),
+                TransformStep::Translate(Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -10.0,
+                }),
+            ],
+            sphere(),
+        );
+        let h = moved.closest_hit(down_z(0.0), 0.0, f32::MAX).unwrap();
+        assert!((h.t - 13.0).abs() < 1e-4, "{:?}", h);
+        assert!((h.n.z - 1.0).abs() < 1e-4 && h.front_face, "{:?}", h);
+
+        // x^2 / 4 + z^2 = 1, with the normal along (x / 4, 0, z).
+        let stretched = Transform::new(
+            vec![TransformStep::Scale(Scale::PerAxis(Vec3 {
+                x: 2.0,
+                y: 1.0,
+                z: 1.0,
+            }))],
+            sphere(),
+        );
+        let h = stretched.closest_hit(down_z(1.0), 0.0, f32::MAX).unwrap();
+        let z = 0.75f32.sqrt();
+        let n = Vec3 { x: 0.25, y: 0.0, z }.unit();
+        assert!((h.t - (5.0 - z)).abs() < 1e-4, "{:?}", h);
+        assert!((h.n - n).length() < 1e-4, "{:?}", h);
+    }
+}
@@ -0,0 +1,374 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::error::ValidationError;
+use crate::hittable::area_to_solid_angle_pdf;
+use crate::hittable::validate_point;
+use crate::hittable::HitRecord;
+use crate::hittable::Hittable;
+use crate::hittable::LightSample;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec;
+use crate::vec::cross;
+use crate::vec::dot;
+use crate::vec::Point;
+use crate::vec::Vec3;
+
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Triangle {
+    pub a: Point,
+    pub b: Point,
+    pub c: Point,
+}
+
+struct RayTriangleIntersection {
+    t: f32,
+    // Barycentric weights of b and c.
+    u: f32,
+    v: f32,
+}
+
+// Möller–Trumbore
+fn intersect_triangle_ray(a: Point, b: Point, c: Point, r: Ray) -> Option<RayTriangleIntersection> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = cross(r.d, ac);
+    let det = dot(ab, p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let ao = r.origin - a;
+    let u = dot(ao, p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = cross(ao, ab);
+    let v = dot(r.d, q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    Some(RayTriangleIntersection {
+        t: dot(ac, q) * inv_det,
+        u,
+        v,
+    })
+}
+
+fn triangle_hit_record(r: Ray, t: f32, geometric_n: Vec3, shading_n: Vec3) -> HitRecord {
+    let front_face = dot(geometric_n, r.d) <= 0.0;
+    HitRecord {
+        t,
+        p: r.at(t),
+        n: if front_face { shading_n } else { -shading_n },
+        front_face,
+    }
+}
+
+fn triangle_bounding_box(a: Point, b: Point, c: Point) -> Aabb {
+    Aabb::new(vec::min(vec::min(a, b), c), vec::max(vec::max(a, b), c)).padded()
+}
+
+fn triangle_area(a: Point, b: Point, c: Point) -> f32 {
+    cross(b - a, c - a).length() / 2.0
+}
+
+fn sample_triangle(a: Point, b: Point, c: Point, sampler: &mut Sampler) -> Point {
+    let (u, v) = sampler.get_2d();
+    let su = u.sqrt();
+    (1.0 - su) * a + (su * (1.0 - v)) * b + (su * v) * c
+}
+
+impl Triangle {
+    fn hit_record(&self, r: Ray, t: f32) -> HitRecord {
+        let n = cross(self.b - self.a, self.c - self.a).unit();
+        triangle_hit_record(r, t, n, n)
+    }
+}
+
+#[typetag::serde]
+impl Hittable for Triangle {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        intersect_triangle_ray(self.a, self.b, self.c, r)
+            .map(|i| self.hit_record(r, i.t))
+            .into_iter()
+            .collect()
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        intersect_triangle_ray(self.a, self.b, self.c, r)
+            .filter(|i| t_min <= i.t && i.t <= t_max)
+            .map(|i| self.hit_record(r, i.t))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle_bounding_box(self.a, self.b, self.c))
+    }
+
+    fn sample(&self, origin: Point, sampler: &mut Sampler) -> Option<LightSample> {
+        let n = cross(self.b - self.a, self.c - self.a).unit();
+        let p = sample_triangle(self.a, self.b, self.c, sampler);
+        LightSample::from_area(origin, p, n, triangle_area(self.a, self.b, self.c))
+    }
+
+    fn sample_pdf(&self, origin: Point, h: HitRecord) -> f32 {
+        let n = cross(self.b - self.a, self.c - self.a).unit();
+        area_to_solid_angle_pdf(origin, h.p, n, triangle_area(self.a, self.b, self.c))
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_point(self.a, format!("{}.a", path), errors);
+        validate_point(self.b, format!("{}.b", path), errors);
+        validate_point(self.c, format!("{}.c", path), errors);
+        if triangle_area(self.a, self.b, self.c) == 0.0 {
+            errors.push(ValidationError::new(path, "triangle is degenerate"));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Uv {
+    pub u: f32,
+    pub v: f32,
+}
+
+// Indices into the mesh buffers for each corner of a face. Normals and UVs
+// are indexed separately from positions, as in Wavefront OBJ.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normals: Option<[usize; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uvs: Option<[usize; 3]>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct TriangleMesh {
+    pub vertices: Vec<Point>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub normals: Vec<Vec3>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uvs: Vec<Uv>,
+    pub faces: Vec<MeshFace>,
+    #[serde(skip)]
+    bvh: OnceLock<Bvh>,
+    // Running sum of face areas, for picking faces proportionally to area.
+    #[serde(skip)]
+    area_cdf: OnceLock<Vec<f32>>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        vertices: Vec<Point>,
+        normals: Vec<Vec3>,
+        uvs: Vec<Uv>,
+        faces: Vec<MeshFace>,
+    ) -> Self {
+        Self {
+            vertices,
+            normals,
+            uvs,
+            faces,
+            bvh: OnceLock::new(),
+            area_cdf: OnceLock::new(),
+        }
+    }
+
+    fn area_cdf(&self) -> &[f32] {
+        self.area_cdf.get_or_init(|| {
+            self.faces
+                .iter()
+                .scan(0.0, |total, face| {
+                    let [a, b, c] = self.corners(face);
+                    *total += triangle_area(a, b, c);
+                    Some(*total)
+                })
+                .collect()
+        })
+    }
+
+    fn total_area(&self) -> f32 {
+        self.area_cdf().last().copied().unwrap_or(0.0)
+    }
+
+    fn corners(&self, face: &MeshFace) -> [Point; 3] {
+        face.vertices.map(|i| self.vertices[i])
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| {
+            Bvh::new(
+                self.faces
+                    .iter()
+                    .enumerate()
+                    .map(|(i, face)| {
+                        let [a, b, c] = self.corners(face);
+                        (i, triangle_bounding_box(a, b, c))
+                    })
+                    .collect(),
+            )
+        })
+    }
+
+    fn intersect_face(&self, i: usize, r: Ray) -> Option<RayTriangleIntersection> {
+        let [a, b, c] = self.corners(&self.faces[i]);
+        intersect_triangle_ray(a, b, c, r)
+    }
+
+    fn hit_record(&self, i: usize, r: Ray, hit: RayTriangleIntersection) -> HitRecord {
+        let face = &self.faces[i];
+        let [a, b, c] = self.corners(face);
+        let geometric_n = cross(b - a, c - a).unit();
+        let shading_n = match face.normals {
+            Some([na, nb, nc]) => ((1.0 - hit.u - hit.v) * self.normals[na]
+                + hit.u * self.normals[nb]
+                + hit.v * self.normals[nc])
+                .unit(),
+            None => geometric_n,
+        };
+        triangle_hit_record(r, hit.t, geometric_n, shading_n)
+    }
+}
+
+#[typetag::serde]
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: Ray) -> Vec<HitRecord> {
+        let mut hits = Vec::new();
+        self.bvh().for_each_candidate(r, f32::MIN, f32::MAX, |i| {
+            if let Some(hit) = self.intersect_face(i, r) {
+                hits.push(self.hit_record(i, r, hit));
+            }
+        });
+        hits
+    }
+
+    fn closest_hit(&self, r: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.bvh()
+            .closest_hit(r, t_min, t_max, |i, t_max| {
+                self.intersect_face(i, r)
+                    .filter(|hit| t_min <= hit.t && hit.t <= t_max)
+                    .map(|hit| (hit.t, (i, hit)))
+            })
+            .map(|(i, hit)| self.hit_record(i, r, hit))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh().bounding_box()
+    }
+
+    fn sample(&self, origin: Point, sampler: &mut Sampler) -> Option<LightSample> {
+        let cdf = self.area_cdf();
+        let total = self.total_area();
+        if total <= 0.0 {
+            return None;
+        }
+        let target = sampler.get_1d() * total;
+        let i = cdf.partition_point(|&a| a < target).min(cdf.len() - 1);
+        let [a, b, c] = self.corners(&self.faces[i]);
+        let n = cross(b - a, c - a).unit();
+        LightSample::from_area(origin, sample_triangle(a, b, c, sampler), n, total)
+    }
+
+    // `h.n` may be an interpolated normal, so recover the face the point lies on.
+    fn sample_pdf(&self, origin: Point, h: HitRecord) -> f32 {
+        let r = Ray {
+            origin,
+            d: h.p - origin,
+        };
+        let (t_min, t_max) = (1.0 - 1e-3, 1.0 + 1e-3);
+        self.bvh()
+            .closest_hit(r, t_min, t_max, |i, t_max| {
+                self.intersect_face(i, r)
+                    .filter(|hit| t_min <= hit.t && hit.t <= t_max)
+                    .map(|hit| (hit.t, i))
+            })
+            .map_or(0.0, |i| {
+                let [a, b, c] = self.corners(&self.faces[i]);
+                let n = cross(b - a, c - a).unit();
+                area_to_solid_angle_pdf(origin, h.p, n, self.total_area())
+            })
+    }
+
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (i, v) in self.vertices.iter().enumerate() {
+            validate_point(*v, format!("{}.vertices[{}]", path, i), errors);
+        }
+        for (i, face) in self.faces.iter().enumerate() {
+            let indices = [
+                ("vertices", Some(face.vertices), self.vertices.len()),
+                ("normals", face.normals, self.normals.len()),
+                ("uvs", face.uvs, self.uvs.len()),
+            ];
+            for (field, corners, len) in indices {
+                if corners.is_some_and(|c| c.iter().any(|&k| k >= len)) {
+                    errors.push(ValidationError::new(
+                        format!("{}.faces[{}].{}", path, i, field),
+                        format!("index out of range, {} has {} entries", field, len),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Triangle;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::vec::Point;
+    use crate::vec::Vec3;
+
+    #[test]
+    fn test_triangle_closest_hit() {
+        let triangle = Triangle {
+            a: Point {
+                x: -1.0,
+                y: -1.0,
+                z: -2.0,
+            },
+            b: Point {
+                x: 1.0,
+                y: -1.0,
+                z: -2.0,
+            },
+            c: Point {
+                x: 0.0,
+                y: 1.0,
+                z: -2.0,
+            },
+        };
+        let r = Ray {
+            origin: Point {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            d: Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -1.0,
+            },
+        };
+
+        let h = triangle.closest_hit(r, 0.0, f32::MAX).unwrap();
+        assert!((h.t - 2.0).abs() < 1e-6);
+        assert!(h.front_face);
+        assert_eq!(
+            h.n,
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            }
+        );
+        assert!(triangle.closest_hit(r, 0.0, 1.0).is_none());
+    }
+}
@@ -1,5 +1,4 @@
 use serde::{Deserialize, Serialize};
-use std::fmt;
 use std::ops;
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
@@ -21,6 +20,41 @@ pub fn cross(u: Vec3, v: Vec3) -> Vec3 {
     }
 }
 
+pub fn min(u: Vec3, v: Vec3) -> Vec3 {
+    Vec3 {
+        x: u.x.min(v.x),
+        y: u.y.min(v.y),
+        z: u.z.min(v.z),
+    }
+}
+
+pub fn max(u: Vec3, v: Vec3) -> Vec3 {
+    Vec3 {
+        x: u.x.max(v.x),
+        y: u.y.max(v.y),
+        z: u.z.max(v.z),
+    }
+}
+
+// Two unit vectors completing the unit vector `w` to an orthonormal basis.
+pub fn orthonormal_basis(w: Vec3) -> (Vec3, Vec3) {
+    let a = if w.x.abs() > 0.9 {
+        Vec3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        }
+    } else {
+        Vec3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    };
+    let v = cross(w, a).unit();
+    (cross(v, w), v)
+}
+
 impl ops::Add for Vec3 {
     type Output = Self;
 
@@ -97,6 +131,19 @@ impl ops::Div<f32> for Vec3 {
     }
 }
 
+impl ops::Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, axis: usize) -> &f32 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of range: {}", axis),
+        }
+    }
+}
+
 impl Vec3 {
     pub fn length(&self) -> f32 {
         self.length_squared().sqrt()
@@ -109,13 +156,9 @@ impl Vec3 {
     pub fn unit(&self) -> Self {
         *self / self.length()
     }
-}
 
-impl fmt::Display for Vec3 {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let m = 255.999;
-        let vn = *self * m;
-        write!(f, "{} {} {}", vn.x as i32, vn.y as i32, vn.z as i32)
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
     }
 }
 